@@ -1,25 +1,75 @@
 use {
-    borsh::{BorshDeserialize, BorshSerialize},
     dotenv::dotenv,
     lazy_static::lazy_static,
-    solana_client::{client_error::Result, rpc_client::RpcClient},
-    solana_program::system_instruction,
-    solana_sdk::{
-        commitment_config::CommitmentConfig,
-        instruction::{AccountMeta, Instruction},
-        pubkey::Pubkey,
-        signature::{Keypair, Signer},
-        transaction::Transaction,
+    solana_account_decoder::UiAccountEncoding,
+    solana_client::{
+        client_error::ClientError,
+        rpc_client::RpcClient,
+        rpc_config::{RpcAccountInfoConfig, RpcProgramAccountsConfig},
+        rpc_filter::{Memcmp, RpcFilterType},
     },
-    std::str::FromStr,
+    solana_sdk::{instruction::InstructionError, pubkey::Pubkey, transaction::TransactionError},
+    vault_client::{Vault, VaultError},
+    vault_program::state,
 };
 
 fn main() {}
 
-#[derive(BorshSerialize, BorshDeserialize)]
-struct ProgramData {
-    code: u8,
-    amount: u64,
+/// Extracts the vault program error from a failed transaction, if any.
+#[allow(dead_code)]
+fn vault_error(error: &ClientError) -> Option<VaultError> {
+    match error.get_transaction_error()? {
+        TransactionError::InstructionError(_, InstructionError::Custom(code)) => {
+            VaultError::from_code(code)
+        }
+        _ => None,
+    }
+}
+
+/// Lists every vault of `owner`, default and named, with one `getProgramAccounts` call.
+#[allow(clippy::result_large_err)]
+#[allow(dead_code)]
+fn list_vaults(
+    client: &RpcClient,
+    program_id: &Pubkey,
+    owner: &Pubkey,
+) -> Result<Vec<(Pubkey, Vault)>, ClientError> {
+    let config = RpcProgramAccountsConfig {
+        filters: Some(vec![
+            RpcFilterType::Memcmp(Memcmp::new_base58_encoded(0, &state::VAULT_DISCRIMINATOR)),
+            RpcFilterType::Memcmp(Memcmp::new_base58_encoded(
+                state::VAULT_OWNER_OFFSET,
+                owner.as_ref(),
+            )),
+        ]),
+        account_config: RpcAccountInfoConfig {
+            encoding: Some(UiAccountEncoding::Base64),
+            ..RpcAccountInfoConfig::default()
+        },
+        ..RpcProgramAccountsConfig::default()
+    };
+
+    let vaults = client
+        .get_program_accounts_with_config(program_id, config)?
+        .into_iter()
+        .filter_map(|(address, account)| {
+            vault_client::decode_vault(&account.data)
+                .ok()
+                .map(|vault| (address, vault))
+        })
+        .collect();
+
+    Ok(vaults)
+}
+
+/// Fee treasury of the program config, once the config is initialized.
+#[allow(dead_code)]
+fn treasury(client: &RpcClient, program_id: &Pubkey) -> Option<Pubkey> {
+    client
+        .get_account_data(&vault_client::config_address(program_id).0)
+        .ok()
+        .and_then(|data| vault_client::decode_config(&data).ok())
+        .map(|config| config.treasury)
 }
 
 lazy_static! {
@@ -45,7 +95,15 @@ lazy_static! {
 
 #[cfg(test)]
 mod client_tests {
-    use super::*;
+    use {
+        super::*,
+        solana_sdk::{
+            commitment_config::CommitmentConfig,
+            signature::{Keypair, Signer},
+            transaction::Transaction,
+        },
+        std::str::FromStr,
+    };
 
     #[test]
     fn test_balance() {
@@ -57,26 +115,10 @@ mod client_tests {
         let payer = Keypair::from_base58_string(KEYPAIR.as_str());
         println!("payer: {}", payer.pubkey());
 
-        let (receiver, bump_seed) = solana_program::pubkey::Pubkey::find_program_address(
-            &[payer.pubkey().as_ref()],
-            &program_id,
-        );
+        let (receiver, _bump_seed) = vault_client::vault_address(&program_id, &payer.pubkey(), &[]);
         println!("receiver: {}", receiver);
 
-        let instruction_data = ProgramData {
-            code: 1,   // balance
-            amount: 0, // 0 sol
-        };
-
-        let instruction = Instruction::new_with_borsh(
-            program_id,
-            &instruction_data,
-            vec![
-                AccountMeta::new(payer.pubkey(), true),
-                AccountMeta::new(receiver, false),
-                AccountMeta::new(solana_program::system_program::ID, false),
-            ],
-        );
+        let instruction = vault_client::balance_ix(&program_id, &payer.pubkey(), &[]);
         println!("instruction: {:#?}", instruction);
 
         let transaction = Transaction::new_signed_with_payer(
@@ -90,6 +132,10 @@ mod client_tests {
         let tx = client.send_and_confirm_transaction(&transaction);
         println!("tx: {:#?}", tx);
 
+        if let Err(error) = &tx {
+            println!("vault error: {:?}", vault_error(error));
+        }
+
         assert!(tx.is_ok())
     }
 
@@ -103,25 +149,15 @@ mod client_tests {
         let payer = Keypair::from_base58_string(KEYPAIR.as_str());
         println!("payer: {}", payer.pubkey());
 
-        let (receiver, bump_seed) = solana_program::pubkey::Pubkey::find_program_address(
-            &[payer.pubkey().as_ref()],
-            &program_id,
-        );
+        let (receiver, _bump_seed) = vault_client::vault_address(&program_id, &payer.pubkey(), &[]);
         println!("receiver: {}", receiver);
 
-        let instruction_data = ProgramData {
-            code: 2,         // deposit
-            amount: 100_000, // 0.0001 sol
-        };
-
-        let instruction = Instruction::new_with_borsh(
-            program_id,
-            &instruction_data,
-            vec![
-                AccountMeta::new(payer.pubkey(), true),
-                AccountMeta::new(receiver, false),
-                AccountMeta::new(solana_program::system_program::ID, false),
-            ],
+        let instruction = vault_client::deposit_ix(
+            &program_id,
+            &payer.pubkey(),
+            &[],
+            100_000, // 0.0001 sol
+            treasury(&client, &program_id).as_ref(),
         );
         println!("instruction: {:#?}", instruction);
 
@@ -133,6 +169,10 @@ mod client_tests {
         let tx = client.send_and_confirm_transaction(&transaction);
         println!("tx: {:#?}", tx);
 
+        if let Err(error) = &tx {
+            println!("vault error: {:?}", vault_error(error));
+        }
+
         assert!(tx.is_ok())
     }
 
@@ -146,25 +186,15 @@ mod client_tests {
         let payer = Keypair::from_base58_string(KEYPAIR.as_str());
         println!("payer: {}", payer.pubkey());
 
-        let (receiver, bump_seed) = solana_program::pubkey::Pubkey::find_program_address(
-            &[payer.pubkey().as_ref()],
-            &program_id,
-        );
+        let (receiver, _bump_seed) = vault_client::vault_address(&program_id, &payer.pubkey(), &[]);
         println!("receiver: {}", receiver);
 
-        let instruction_data = ProgramData {
-            code: 3,         // withdraw
-            amount: 100_000, // 0.0001 sol
-        };
-
-        let instruction = Instruction::new_with_borsh(
-            program_id,
-            &instruction_data,
-            vec![
-                AccountMeta::new(payer.pubkey(), true),
-                AccountMeta::new(receiver, false),
-                AccountMeta::new(solana_program::system_program::ID, false),
-            ],
+        let instruction = vault_client::withdraw_ix(
+            &program_id,
+            &payer.pubkey(),
+            &[],
+            100_000, // 0.0001 sol
+            treasury(&client, &program_id).as_ref(),
         );
         println!("instruction: {:#?}", instruction);
 
@@ -176,6 +206,42 @@ mod client_tests {
         let tx = client.send_and_confirm_transaction(&transaction);
         println!("tx: {:#?}", tx);
 
+        if let Err(error) = &tx {
+            println!("vault error: {:?}", vault_error(error));
+        }
+
         assert!(tx.is_ok())
     }
+
+    #[test]
+    fn test_simulate_balance() {
+        let program_id = Pubkey::from_str(PROGRAM_ID.as_str()).unwrap();
+
+        let client =
+            RpcClient::new_with_commitment(RPC_URL.as_str(), CommitmentConfig::confirmed());
+
+        let payer = Keypair::from_base58_string(KEYPAIR.as_str());
+        println!("payer: {}", payer.pubkey());
+
+        let vault = vault_client::simulate_balance(&client, &program_id, &payer.pubkey(), &[]);
+        println!("vault: {:#?}", vault);
+
+        assert!(vault.is_ok())
+    }
+
+    #[test]
+    fn test_list_vaults() {
+        let program_id = Pubkey::from_str(PROGRAM_ID.as_str()).unwrap();
+
+        let client =
+            RpcClient::new_with_commitment(RPC_URL.as_str(), CommitmentConfig::confirmed());
+
+        let payer = Keypair::from_base58_string(KEYPAIR.as_str());
+        println!("payer: {}", payer.pubkey());
+
+        let vaults = list_vaults(&client, &program_id, &payer.pubkey());
+        println!("vaults: {:#?}", vaults);
+
+        assert!(vaults.is_ok())
+    }
 }
@@ -0,0 +1,307 @@
+use num_traits::FromPrimitive;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, thiserror::Error, num_derive::FromPrimitive)]
+pub enum VaultError {
+    #[error("Vault account does not match the derived PDA")]
+    InvalidVaultAddress = 0,
+
+    #[error("Amount must be greater than zero")]
+    ZeroAmount = 1,
+
+    #[error("Vault balance is lower than the requested amount")]
+    InsufficientVaultBalance = 2,
+
+    #[error("User account cannot cover the vault rent")]
+    InsufficientFundsForRent = 3,
+
+    #[error("Vault balance overflow")]
+    BalanceOverflow = 4,
+
+    #[error("Account is not a vault")]
+    InvalidAccountDiscriminator = 5,
+
+    #[error("Vault layout version is not supported")]
+    UnsupportedVaultVersion = 6,
+
+    #[error("Signer is not the vault owner")]
+    InvalidVaultOwner = 7,
+
+    #[error("Vault already uses the current layout")]
+    AlreadyMigrated = 8,
+
+    #[error("Token balance account does not match the derived PDA")]
+    InvalidTokenBalanceAddress = 9,
+
+    #[error("Token account is not the expected associated token account")]
+    InvalidTokenAccount = 10,
+
+    #[error("Mint uses an extension the vault cannot safely hold")]
+    UnsupportedMintExtension = 11,
+
+    #[error("Vault name is malformed or too long")]
+    InvalidVaultName = 12,
+
+    #[error("Withdrawal would take the vault below rent exemption")]
+    ExceedsAvailableLamports = 13,
+
+    #[error("Vault already exists")]
+    VaultAlreadyExists = 14,
+
+    #[error("Vault is locked")]
+    VaultLocked = 15,
+
+    #[error("Vault lock can only be extended")]
+    LockShortened = 16,
+
+    #[error("Vesting schedule must satisfy start <= cliff <= end and start < end")]
+    InvalidVestingSchedule = 17,
+
+    #[error("Vault already holds a grant that has not fully vested")]
+    VestingActive = 18,
+
+    #[error("Signer is not the grantor of the vault's vesting schedule")]
+    InvalidVestingGrantor = 19,
+
+    #[error("Vesting schedule is not revocable")]
+    VestingNotRevocable = 20,
+
+    #[error("Amount exceeds the vault's vested balance")]
+    InsufficientVestedBalance = 21,
+
+    #[error("Stream account does not match the derived PDA")]
+    InvalidStreamAddress = 22,
+
+    #[error("Stream already exists")]
+    StreamAlreadyExists = 23,
+
+    #[error("Multisig vault funds can only move through approved proposals")]
+    MultisigRequired = 24,
+
+    #[error(
+        "Multisig owners must be unique, at most ten, with a threshold between one and their count"
+    )]
+    InvalidMultisigConfig = 25,
+
+    #[error("Multisig account does not match the derived PDA")]
+    InvalidMultisigAddress = 26,
+
+    #[error("Signer is not a multisig owner")]
+    NotMultisigOwner = 27,
+
+    #[error("Proposal account does not match the derived PDA")]
+    InvalidProposalAddress = 28,
+
+    #[error("Proposal was already executed")]
+    ProposalExecuted = 29,
+
+    #[error("Proposal has expired")]
+    ProposalExpired = 30,
+
+    #[error("Proposal does not have enough approvals")]
+    InsufficientApprovals = 31,
+
+    #[error("Allowance account does not match the derived PDA")]
+    InvalidAllowanceAddress = 32,
+
+    #[error("Amount exceeds the delegate's allowance")]
+    InsufficientAllowance = 33,
+
+    #[error("Allowance has expired")]
+    AllowanceExpired = 34,
+
+    #[error("Withdrawal exceeds the vault's remaining limit for the current window")]
+    WithdrawalLimitExceeded = 35,
+
+    #[error("Allowlist account does not match the derived PDA or is missing")]
+    InvalidAllowlistAddress = 36,
+
+    #[error("Destination is not on the vault's allowlist or still in its cooldown")]
+    DestinationNotAllowed = 37,
+
+    #[error("Destination is already on the allowlist")]
+    DestinationAlreadyListed = 38,
+
+    #[error("Allowlist is full")]
+    AllowlistFull = 39,
+
+    #[error("Guardians must be unique, at most ten, with a threshold between one and their count")]
+    InvalidGuardianConfig = 40,
+
+    #[error("Guardians account does not match the derived PDA")]
+    InvalidGuardiansAddress = 41,
+
+    #[error("Signer is not a guardian of the vault")]
+    NotGuardian = 42,
+
+    #[error("Recovery lacks guardian approvals or is still timelocked")]
+    RecoveryNotReady = 43,
+
+    #[error("No recovery is pending")]
+    NoPendingRecovery = 44,
+
+    #[error("Config account does not match the derived PDA or is missing")]
+    InvalidConfigAddress = 45,
+
+    #[error("Program is paused")]
+    ProgramPaused = 46,
+
+    #[error("Signer is not the program upgrade authority")]
+    InvalidUpgradeAuthority = 47,
+
+    #[error("Signer is not the config admin")]
+    InvalidConfigAdmin = 48,
+
+    #[error("Fee exceeds 10000 basis points")]
+    InvalidFee = 49,
+
+    #[error("Treasury account does not match the config or is missing")]
+    InvalidTreasuryAddress = 50,
+
+    #[error("Account must be writable")]
+    AccountNotWritable = 51,
+
+    #[error("Account is owned by an unexpected program")]
+    InvalidAccountOwner = 52,
+
+    #[error("Account is not the expected program")]
+    InvalidProgramAccount = 53,
+
+    #[error("Recovery has to rotate to a non-default authority")]
+    InvalidRecoveryAuthority = 54,
+
+    #[error("Account was left behind by a closed vault at the same address")]
+    StaleCompanionAccount = 55,
+
+    #[error("Multisig proposals cannot move the tokens the vault holds")]
+    TokensHeld = 56,
+
+    #[error("Account must sign the transaction")]
+    MissingSignature = 57,
+}
+
+impl VaultError {
+    /// Decodes a custom error code returned by the vault program.
+    pub fn from_code(code: u32) -> Option<Self> {
+        Self::from_u32(code)
+    }
+
+    /// Decodes a `ProgramError::Custom` produced by the vault program.
+    pub fn from_program_error(error: &solana_program::program_error::ProgramError) -> Option<Self> {
+        match error {
+            solana_program::program_error::ProgramError::Custom(code) => Self::from_code(*code),
+            _ => None,
+        }
+    }
+}
+
+impl From<VaultError> for solana_program::program_error::ProgramError {
+    fn from(error: VaultError) -> Self {
+        solana_program::program_error::ProgramError::Custom(error as u32)
+    }
+}
+
+impl TryFrom<u32> for VaultError {
+    type Error = solana_program::program_error::ProgramError;
+
+    fn try_from(code: u32) -> Result<Self, Self::Error> {
+        Self::from_code(code).ok_or(solana_program::program_error::ProgramError::InvalidArgument)
+    }
+}
+
+impl<T> solana_program::decode_error::DecodeError<T> for VaultError {
+    fn type_of() -> &'static str {
+        "VaultError"
+    }
+}
+
+impl solana_program::program_error::PrintProgramError for VaultError {
+    fn print<E>(&self)
+    where
+        E: 'static
+            + std::error::Error
+            + solana_program::decode_error::DecodeError<E>
+            + solana_program::program_error::PrintProgramError
+            + FromPrimitive,
+    {
+        solana_program::msg!("Vault error: {}", self);
+    }
+}
+
+#[cfg(test)]
+mod error_tests {
+    use super::*;
+
+    #[test]
+    fn test_custom_code_roundtrip() {
+        for error in [
+            VaultError::InvalidVaultAddress,
+            VaultError::ZeroAmount,
+            VaultError::InsufficientVaultBalance,
+            VaultError::InsufficientFundsForRent,
+            VaultError::BalanceOverflow,
+            VaultError::InvalidAccountDiscriminator,
+            VaultError::UnsupportedVaultVersion,
+            VaultError::InvalidVaultOwner,
+            VaultError::AlreadyMigrated,
+            VaultError::InvalidTokenBalanceAddress,
+            VaultError::InvalidTokenAccount,
+            VaultError::UnsupportedMintExtension,
+            VaultError::InvalidVaultName,
+            VaultError::ExceedsAvailableLamports,
+            VaultError::VaultAlreadyExists,
+            VaultError::VaultLocked,
+            VaultError::LockShortened,
+            VaultError::InvalidVestingSchedule,
+            VaultError::VestingActive,
+            VaultError::InvalidVestingGrantor,
+            VaultError::VestingNotRevocable,
+            VaultError::InsufficientVestedBalance,
+            VaultError::InvalidStreamAddress,
+            VaultError::StreamAlreadyExists,
+            VaultError::MultisigRequired,
+            VaultError::InvalidMultisigConfig,
+            VaultError::InvalidMultisigAddress,
+            VaultError::NotMultisigOwner,
+            VaultError::InvalidProposalAddress,
+            VaultError::ProposalExecuted,
+            VaultError::ProposalExpired,
+            VaultError::InsufficientApprovals,
+            VaultError::InvalidAllowanceAddress,
+            VaultError::InsufficientAllowance,
+            VaultError::AllowanceExpired,
+            VaultError::WithdrawalLimitExceeded,
+            VaultError::InvalidAllowlistAddress,
+            VaultError::DestinationNotAllowed,
+            VaultError::DestinationAlreadyListed,
+            VaultError::AllowlistFull,
+            VaultError::InvalidGuardianConfig,
+            VaultError::InvalidGuardiansAddress,
+            VaultError::NotGuardian,
+            VaultError::RecoveryNotReady,
+            VaultError::NoPendingRecovery,
+            VaultError::InvalidConfigAddress,
+            VaultError::ProgramPaused,
+            VaultError::InvalidUpgradeAuthority,
+            VaultError::InvalidConfigAdmin,
+            VaultError::InvalidFee,
+            VaultError::InvalidTreasuryAddress,
+            VaultError::AccountNotWritable,
+            VaultError::InvalidAccountOwner,
+            VaultError::InvalidProgramAccount,
+            VaultError::InvalidRecoveryAuthority,
+            VaultError::StaleCompanionAccount,
+            VaultError::TokensHeld,
+            VaultError::MissingSignature,
+        ] {
+            let program_error = solana_program::program_error::ProgramError::from(error);
+
+            assert_eq!(VaultError::from_program_error(&program_error), Some(error));
+        }
+    }
+
+    #[test]
+    fn test_unknown_code() {
+        assert_eq!(VaultError::from_code(u32::MAX), None);
+        assert!(VaultError::try_from(u32::MAX).is_err());
+    }
+}
@@ -0,0 +1,116 @@
+use borsh::{BorshDeserialize, BorshSerialize};
+
+/// Prefixes every event the program logs with `sol_log_data`.
+pub const EVENT_DISCRIMINATOR: [u8; 8] = *b"vaultevt";
+
+/// Current schema version of [`VaultEvent`]. Variants and fields are only
+/// ever appended; any other change bumps the version.
+pub const EVENT_VERSION: u8 = 1;
+
+/// Length of the discriminator and version header preceding the Borsh
+/// encoded event.
+pub const EVENT_HEADER_LEN: usize = 8 + 1;
+
+/// An event logged as `[discriminator][version][borsh(VaultEvent)]`, one
+/// `Program data:` line per event.
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub enum VaultEvent {
+    VaultCreated(VaultCreated),
+    Deposit(Deposit),
+    Withdraw(Withdraw),
+    BalanceAdjusted(BalanceAdjusted),
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub struct VaultCreated {
+    pub vault: solana_program::pubkey::Pubkey,
+    pub owner: solana_program::pubkey::Pubkey,
+    /// Account that funded the vault rent.
+    pub payer: solana_program::pubkey::Pubkey,
+    pub name: Vec<u8>,
+    pub timestamp: i64,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub struct Deposit {
+    pub vault: solana_program::pubkey::Pubkey,
+    pub depositor: solana_program::pubkey::Pubkey,
+    /// Lamports credited to the vault balance, after the fee.
+    pub amount: u64,
+    /// Protocol fee paid to the treasury on top of `amount`.
+    pub fee: u64,
+    /// Vault balance after the deposit.
+    pub balance: u64,
+    pub timestamp: i64,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub struct Withdraw {
+    pub vault: solana_program::pubkey::Pubkey,
+    pub destination: solana_program::pubkey::Pubkey,
+    /// Lamports debited from the vault balance, including the fee.
+    pub amount: u64,
+    /// Protocol fee paid to the treasury out of `amount`.
+    pub fee: u64,
+    /// Vault balance after the withdrawal.
+    pub balance: u64,
+    pub timestamp: i64,
+}
+
+/// A balance change outside deposits and withdrawals: lamports booked by
+/// `Sync`, the stored balance of a migrated legacy vault, or a token balance
+/// moved by a token deposit or withdrawal.
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub struct BalanceAdjusted {
+    pub vault: solana_program::pubkey::Pubkey,
+    /// Mint of the adjusted token balance, the default key for lamports.
+    pub mint: solana_program::pubkey::Pubkey,
+    pub previous_balance: u64,
+    pub balance: u64,
+    pub timestamp: i64,
+}
+
+impl VaultEvent {
+    pub fn pack(&self) -> Vec<u8> {
+        let mut data = Vec::with_capacity(EVENT_HEADER_LEN + 128);
+        data.extend_from_slice(&EVENT_DISCRIMINATOR);
+        data.push(EVENT_VERSION);
+        self.serialize(&mut data)
+            .expect("writing to a Vec cannot fail");
+
+        data
+    }
+
+    pub fn emit(&self) {
+        solana_program::log::sol_log_data(&[&self.pack()]);
+    }
+}
+
+#[cfg(test)]
+mod event_tests {
+    use super::*;
+
+    #[test]
+    fn test_pack_layout() {
+        let event = VaultEvent::Withdraw(Withdraw {
+            vault: solana_program::pubkey::Pubkey::new_unique(),
+            destination: solana_program::pubkey::Pubkey::new_unique(),
+            amount: 400_000,
+            fee: 2_000,
+            balance: 600_000,
+            timestamp: 1_700_000_000,
+        });
+
+        let data = event.pack();
+
+        assert_eq!(data[..8], EVENT_DISCRIMINATOR);
+        assert_eq!(data[8], EVENT_VERSION);
+        // Variant index, then the fields in declaration order.
+        assert_eq!(data[EVENT_HEADER_LEN], 2);
+        assert_eq!(data.len(), EVENT_HEADER_LEN + 1 + 32 + 32 + 8 * 4);
+        assert_eq!(
+            VaultEvent::try_from_slice(&data[EVENT_HEADER_LEN..]).unwrap(),
+            event,
+        );
+    }
+}
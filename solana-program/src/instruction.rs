@@ -0,0 +1,324 @@
+use borsh::{BorshDeserialize, BorshSerialize};
+
+/// Instructions of the vault program, Borsh encoded as a one-byte code
+/// followed by the variant's fields.
+///
+/// Every vault instruction names the vault it acts on; an empty
+/// `vault_name` selects the owner's default vault. The config instructions
+/// act on the program config instead.
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+#[borsh(use_discriminant = true)]
+#[repr(u8)]
+pub enum VaultInstruction {
+    Balance {
+        vault_name: Vec<u8>,
+    } = 1,
+    Deposit {
+        vault_name: Vec<u8>,
+        amount: u64,
+    } = 2,
+    Withdraw {
+        vault_name: Vec<u8>,
+        amount: u64,
+    } = 3,
+    Migrate {
+        vault_name: Vec<u8>,
+    } = 4,
+    DepositToken {
+        vault_name: Vec<u8>,
+        amount: u64,
+    } = 5,
+    WithdrawToken {
+        vault_name: Vec<u8>,
+        amount: u64,
+    } = 6,
+    Close {
+        vault_name: Vec<u8>,
+    } = 7,
+    Sync {
+        vault_name: Vec<u8>,
+    } = 8,
+    WithdrawTo {
+        vault_name: Vec<u8>,
+        amount: u64,
+    } = 9,
+    CreateVault {
+        vault_name: Vec<u8>,
+    } = 10,
+    SetLock {
+        vault_name: Vec<u8>,
+        lock_kind: LockKind,
+        unlock_at: u64,
+    } = 11,
+    GrantVesting {
+        vault_name: Vec<u8>,
+        /// Total lamports granted.
+        amount: u64,
+        terms: VestingTerms,
+    } = 12,
+    RevokeVesting {
+        vault_name: Vec<u8>,
+    } = 13,
+    OpenStream {
+        vault_name: Vec<u8>,
+        /// Lamports moved from the vault into the stream.
+        amount: u64,
+        stream_id: u64,
+        /// Lamports released per second.
+        rate: u64,
+    } = 14,
+    ClaimStream {
+        vault_name: Vec<u8>,
+        stream_id: u64,
+    } = 15,
+    CancelStream {
+        vault_name: Vec<u8>,
+        stream_id: u64,
+    } = 16,
+    EnableMultisig {
+        vault_name: Vec<u8>,
+        threshold: u8,
+        owners: Vec<solana_program::pubkey::Pubkey>,
+    } = 17,
+    Propose {
+        vault_name: Vec<u8>,
+        amount: u64,
+        expires_at: i64,
+    } = 18,
+    Approve {
+        vault_name: Vec<u8>,
+        proposal_id: u64,
+    } = 19,
+    Execute {
+        vault_name: Vec<u8>,
+        proposal_id: u64,
+    } = 20,
+    ApproveDelegate {
+        vault_name: Vec<u8>,
+        amount: u64,
+        expiry_slot: u64,
+    } = 21,
+    RevokeDelegate {
+        vault_name: Vec<u8>,
+    } = 22,
+    SetWithdrawalLimit {
+        vault_name: Vec<u8>,
+        /// Lamports per rolling window; zero removes the limit.
+        limit: u64,
+    } = 23,
+    AddDestination {
+        vault_name: Vec<u8>,
+    } = 24,
+    RemoveDestination {
+        vault_name: Vec<u8>,
+    } = 25,
+    SetAllowlist {
+        vault_name: Vec<u8>,
+        enabled: bool,
+    } = 26,
+    SetGuardians {
+        vault_name: Vec<u8>,
+        threshold: u8,
+        guardians: Vec<solana_program::pubkey::Pubkey>,
+    } = 27,
+    ApproveRecovery {
+        vault_name: Vec<u8>,
+    } = 28,
+    ExecuteRecovery {
+        vault_name: Vec<u8>,
+    } = 29,
+    CancelRecovery {
+        vault_name: Vec<u8>,
+    } = 30,
+    InitializeConfig {
+        deposit_fee_bps: u16,
+        withdraw_fee_bps: u16,
+    } = 31,
+    SetPaused {
+        paused: bool,
+    } = 32,
+    UpdateConfig {
+        new_admin: solana_program::pubkey::Pubkey,
+        treasury: solana_program::pubkey::Pubkey,
+        deposit_fee_bps: u16,
+        withdraw_fee_bps: u16,
+    } = 33,
+    SetFeeExempt {
+        exempt: bool,
+    } = 34,
+}
+
+impl VaultInstruction {
+    /// Decodes instruction data. Unknown codes, truncated fields, trailing
+    /// bytes and out-of-range values are all rejected.
+    pub fn unpack(input: &[u8]) -> Result<Self, solana_program::program_error::ProgramError> {
+        let instruction = Self::try_from_slice(input)
+            .map_err(|_| solana_program::program_error::ProgramError::InvalidInstructionData)?;
+
+        if instruction.vault_name().len() > crate::state::MAX_VAULT_NAME_LEN {
+            return Err(crate::error::VaultError::InvalidVaultName.into());
+        }
+
+        Ok(instruction)
+    }
+
+    pub fn pack(&self) -> Vec<u8> {
+        borsh::to_vec(self).expect("writing to a Vec cannot fail")
+    }
+
+    /// Name of the vault the instruction acts on; empty for the default
+    /// vault and for config instructions.
+    pub fn vault_name(&self) -> &[u8] {
+        match self {
+            Self::Balance { vault_name }
+            | Self::Deposit { vault_name, .. }
+            | Self::Withdraw { vault_name, .. }
+            | Self::Migrate { vault_name }
+            | Self::DepositToken { vault_name, .. }
+            | Self::WithdrawToken { vault_name, .. }
+            | Self::Close { vault_name }
+            | Self::Sync { vault_name }
+            | Self::WithdrawTo { vault_name, .. }
+            | Self::CreateVault { vault_name }
+            | Self::SetLock { vault_name, .. }
+            | Self::GrantVesting { vault_name, .. }
+            | Self::RevokeVesting { vault_name }
+            | Self::OpenStream { vault_name, .. }
+            | Self::ClaimStream { vault_name, .. }
+            | Self::CancelStream { vault_name, .. }
+            | Self::EnableMultisig { vault_name, .. }
+            | Self::Propose { vault_name, .. }
+            | Self::Approve { vault_name, .. }
+            | Self::Execute { vault_name, .. }
+            | Self::ApproveDelegate { vault_name, .. }
+            | Self::RevokeDelegate { vault_name }
+            | Self::SetWithdrawalLimit { vault_name, .. }
+            | Self::AddDestination { vault_name }
+            | Self::RemoveDestination { vault_name }
+            | Self::SetAllowlist { vault_name, .. }
+            | Self::SetGuardians { vault_name, .. }
+            | Self::ApproveRecovery { vault_name }
+            | Self::ExecuteRecovery { vault_name }
+            | Self::CancelRecovery { vault_name } => vault_name,
+            Self::InitializeConfig { .. }
+            | Self::SetPaused { .. }
+            | Self::UpdateConfig { .. }
+            | Self::SetFeeExempt { .. } => &[],
+        }
+    }
+
+    /// Whether the instruction manages the program config. Those take the
+    /// admin and the config PDA in place of the owner and vault accounts.
+    pub fn is_config(&self) -> bool {
+        matches!(
+            self,
+            Self::InitializeConfig { .. }
+                | Self::SetPaused { .. }
+                | Self::UpdateConfig { .. }
+                | Self::SetFeeExempt { .. }
+        )
+    }
+
+    /// Whether the vault owner, the first account, has to sign. A grantor
+    /// revokes a grant, a stream recipient claims or cancels, and multisig
+    /// owners handle proposals and guardians recover a vault on their own
+    /// signature. `Withdraw` is also open to delegates holding an allowance.
+    pub fn requires_owner_signature(&self) -> bool {
+        !matches!(
+            self,
+            Self::Withdraw { .. }
+                | Self::RevokeVesting { .. }
+                | Self::ClaimStream { .. }
+                | Self::CancelStream { .. }
+                | Self::Propose { .. }
+                | Self::Approve { .. }
+                | Self::Execute { .. }
+                | Self::ApproveRecovery { .. }
+                | Self::ExecuteRecovery { .. }
+        )
+    }
+}
+
+/// Terms of a [`VaultInstruction::GrantVesting`] grant.
+#[derive(BorshSerialize, BorshDeserialize, Clone, Copy, Debug, PartialEq)]
+pub struct VestingTerms {
+    pub start: i64,
+    pub cliff: i64,
+    pub end: i64,
+    pub revocable: bool,
+}
+
+/// Unit of the value passed to [`VaultInstruction::SetLock`].
+#[derive(BorshSerialize, BorshDeserialize, Clone, Copy, Debug, PartialEq)]
+#[borsh(use_discriminant = true)]
+pub enum LockKind {
+    UnixTimestamp = 0,
+    Slot = 1,
+}
+
+#[cfg(test)]
+mod instruction_tests {
+    use super::*;
+
+    #[test]
+    fn test_pack_layout() {
+        let balance = VaultInstruction::Balance { vault_name: vec![] };
+
+        assert_eq!(balance.pack(), [1, 0, 0, 0, 0]);
+
+        let deposit = VaultInstruction::Deposit {
+            vault_name: b"payroll".to_vec(),
+            amount: 1_000,
+        };
+        let data = deposit.pack();
+
+        assert_eq!(data[0], 2);
+        assert_eq!(data[1..5], 7u32.to_le_bytes());
+        assert_eq!(data[5..12], *b"payroll");
+        assert_eq!(data[12..], 1_000u64.to_le_bytes());
+        assert_eq!(VaultInstruction::unpack(&data), Ok(deposit));
+    }
+
+    #[test]
+    fn test_unpack_rejects_malformed_input() {
+        let invalid = Err(solana_program::program_error::ProgramError::InvalidInstructionData);
+        let data = VaultInstruction::SetLock {
+            vault_name: vec![],
+            lock_kind: LockKind::Slot,
+            unlock_at: 100,
+        }
+        .pack();
+
+        assert_eq!(VaultInstruction::unpack(&[]), invalid);
+        assert_eq!(VaultInstruction::unpack(&[0]), invalid);
+        assert_eq!(VaultInstruction::unpack(&[35, 0, 0, 0, 0]), invalid);
+        assert_eq!(VaultInstruction::unpack(&data[..data.len() - 1]), invalid);
+        assert_eq!(
+            VaultInstruction::unpack(&[&data[..], &[0]].concat()),
+            invalid
+        );
+
+        let mut unknown_lock_kind = data.clone();
+        unknown_lock_kind[5] = 2;
+        assert_eq!(VaultInstruction::unpack(&unknown_lock_kind), invalid);
+
+        assert_eq!(VaultInstruction::unpack(&[32, 2]), invalid);
+        assert!(VaultInstruction::unpack(&[32, 1]).is_ok());
+    }
+
+    #[test]
+    fn test_unpack_vault_name() {
+        let named = |name: &[u8]| {
+            VaultInstruction::Close {
+                vault_name: name.to_vec(),
+            }
+            .pack()
+        };
+
+        assert!(VaultInstruction::unpack(&named(&[b'a'; 32])).is_ok());
+        assert_eq!(
+            VaultInstruction::unpack(&named(&[b'a'; 33])),
+            Err(crate::error::VaultError::InvalidVaultName.into()),
+        );
+    }
+}
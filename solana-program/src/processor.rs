@@ -0,0 +1,2572 @@
+use solana_program::sysvar::Sysvar;
+
+use crate::{
+    error::VaultError,
+    event::{self, VaultEvent},
+    instruction::VaultInstruction,
+    state, validation,
+};
+
+/// Vault instructions start with `[owner, vault, system program, config, ..]`,
+/// followed by the accounts of the instruction.
+pub fn process(
+    program_id: &solana_program::pubkey::Pubkey,
+    accounts: &[solana_program::account_info::AccountInfo],
+    instruction_data: &[u8],
+) -> solana_program::entrypoint::ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+
+    let user_account = solana_program::account_info::next_account_info(accounts_iter)?;
+    solana_program::msg!(
+        "User account: {}, is signer: {}, is writable: {}",
+        user_account.key,
+        user_account.is_signer,
+        user_account.is_writable,
+    );
+
+    let user_pda_account = solana_program::account_info::next_account_info(accounts_iter)?;
+    solana_program::msg!(
+        "User PDA: {}, is signer: {}, is writable: {}",
+        user_pda_account.key,
+        user_pda_account.is_signer,
+        user_pda_account.is_writable,
+    );
+
+    let instruction = VaultInstruction::unpack(instruction_data)?;
+
+    if instruction.requires_owner_signature() && !user_account.is_signer {
+        return Err(VaultError::MissingSignature.into());
+    }
+
+    let vault_name = instruction.vault_name();
+
+    let system_program = solana_program::account_info::next_account_info(accounts_iter)?;
+    validation::assert_system_program(system_program)?;
+
+    if instruction.is_config() {
+        return process_config(program_id, &instruction, accounts);
+    }
+
+    let config_account = solana_program::account_info::next_account_info(accounts_iter)?;
+
+    let config = load_config(program_id, config_account)?;
+
+    // Balance only reads the vault, so it keeps working while paused.
+    if config.as_ref().is_some_and(|config| config.paused)
+        && !matches!(instruction, VaultInstruction::Balance { .. })
+    {
+        return Err(VaultError::ProgramPaused.into());
+    }
+
+    validation::assert_vault_account(user_pda_account, program_id, &instruction)?;
+
+    solana_program::msg!("Finding PDA");
+
+    let (pda_account, bump_seed) =
+        state::Vault::find_address(program_id, user_account.key, vault_name);
+
+    solana_program::msg!("Found PDA: {}, seed: {}", pda_account, bump_seed);
+
+    solana_program::msg!("Checking PDA validity");
+
+    let bump_seed = match user_pda_account.key == &pda_account {
+        true => bump_seed,
+        false => recovered_vault_bump(program_id, user_account, user_pda_account, vault_name)?,
+    };
+
+    let clock = solana_program::clock::Clock::get()?;
+
+    match &instruction {
+        VaultInstruction::Balance { .. } => {
+            solana_program::msg!("Fetching balance");
+
+            let vault = load_existing_vault(user_account, user_pda_account)?;
+
+            solana_program::msg!("Current balance: {} lamports", vault.balance);
+
+            // The vault layout doubles as the return data, so clients can
+            // read it from a simulation with `Vault::unpack`.
+            let mut return_data = [0u8; state::Vault::LEN];
+            vault.pack(&mut return_data)?;
+            solana_program::program::set_return_data(&return_data);
+        }
+
+        VaultInstruction::Deposit { amount, .. } => {
+            solana_program::msg!("Processing deposit");
+
+            validation::assert_writable(user_account)?;
+
+            let mut vault = load_vault(
+                program_id,
+                user_account,
+                user_pda_account,
+                bump_seed,
+                vault_name,
+                &clock,
+            )?;
+
+            let deposit_amount = *amount;
+
+            solana_program::msg!("Lamports to deposit: {}", deposit_amount);
+
+            if deposit_amount == 0 {
+                return Err(VaultError::ZeroAmount.into());
+            }
+
+            let fee = protocol_fee(accounts, config.as_ref(), &vault, |config| {
+                config.deposit_fee(deposit_amount)
+            })?;
+            let fee_amount = fee.map_or(0, |(_, fee_amount)| fee_amount);
+            let net_amount = deposit_amount - fee_amount;
+
+            solana_program::program::invoke(
+                &solana_program::system_instruction::transfer(
+                    user_account.key,
+                    user_pda_account.key,
+                    net_amount,
+                ),
+                &[user_account.clone(), user_pda_account.clone()],
+            )?;
+
+            if let Some((treasury_account, fee_amount)) = fee {
+                solana_program::program::invoke(
+                    &solana_program::system_instruction::transfer(
+                        user_account.key,
+                        treasury_account.key,
+                        fee_amount,
+                    ),
+                    &[user_account.clone(), treasury_account.clone()],
+                )?;
+            }
+
+            vault.deposit(net_amount, clock.unix_timestamp)?;
+            vault.pack(&mut user_pda_account.try_borrow_mut_data()?)?;
+
+            VaultEvent::Deposit(event::Deposit {
+                vault: *user_pda_account.key,
+                depositor: *user_account.key,
+                amount: net_amount,
+                fee: fee_amount,
+                balance: vault.balance,
+                timestamp: clock.unix_timestamp,
+            })
+            .emit();
+
+            solana_program::msg!(
+                "Deposit successful. New PDA balance: {}, fee: {} lamports",
+                vault.balance,
+                fee_amount,
+            );
+        }
+
+        VaultInstruction::Withdraw { amount, .. } if !user_account.is_signer => {
+            solana_program::msg!("Processing delegate withdrawal");
+
+            let mut vault = load_existing_vault(user_account, user_pda_account)?;
+            vault.ensure_single_owner()?;
+            vault.ensure_unlocked(&clock)?;
+
+            let (Some(delegate_account), Some(allowance_account)) =
+                (accounts_iter.next(), accounts_iter.next())
+            else {
+                return Err(VaultError::MissingSignature.into());
+            };
+
+            validation::assert_writable_signer(delegate_account)?;
+
+            check_allowance_address(
+                program_id,
+                user_pda_account,
+                delegate_account,
+                allowance_account,
+            )?;
+            validation::assert_state_account(allowance_account, program_id)?;
+
+            let mut allowance = state::Allowance::unpack(&allowance_account.try_borrow_data()?)?;
+            vault.ensure_companion(allowance.vault_created_slot)?;
+
+            let withdraw_amount = *amount;
+
+            solana_program::msg!(
+                "Lamports to withdraw: {}, delegate: {}, allowance: {}",
+                withdraw_amount,
+                delegate_account.key,
+                allowance.amount,
+            );
+
+            if withdraw_amount == 0 {
+                return Err(VaultError::ZeroAmount.into());
+            }
+
+            allowance.spend(withdraw_amount, &clock)?;
+            allowance.pack(&mut allowance_account.try_borrow_mut_data()?)?;
+
+            check_destination(
+                program_id,
+                accounts,
+                &vault,
+                user_pda_account,
+                delegate_account.key,
+                &clock,
+            )?;
+
+            let fee = protocol_fee(accounts, config.as_ref(), &vault, |config| {
+                config.withdraw_fee(withdraw_amount)
+            })?;
+
+            withdraw_lamports(
+                &mut vault,
+                user_pda_account,
+                delegate_account,
+                withdraw_amount,
+                fee,
+                &clock,
+            )?;
+
+            solana_program::msg!(
+                "Delegate withdrawal successful. New PDA balance: {}, allowance left: {}, fee: {} lamports",
+                vault.balance,
+                allowance.amount,
+                fee.map_or(0, |(_, fee_amount)| fee_amount),
+            );
+        }
+
+        VaultInstruction::Withdraw { amount, .. } => {
+            solana_program::msg!("Processing withdrawal");
+
+            validation::assert_writable(user_account)?;
+
+            let mut vault = load_vault(
+                program_id,
+                user_account,
+                user_pda_account,
+                bump_seed,
+                vault_name,
+                &clock,
+            )?;
+
+            vault.ensure_single_owner()?;
+            vault.ensure_unlocked(&clock)?;
+
+            let withdraw_amount = *amount;
+
+            solana_program::msg!("Lamports to withdraw: {}", withdraw_amount);
+
+            if withdraw_amount == 0 {
+                return Err(VaultError::ZeroAmount.into());
+            }
+
+            check_destination(
+                program_id,
+                accounts,
+                &vault,
+                user_pda_account,
+                user_account.key,
+                &clock,
+            )?;
+
+            let fee = protocol_fee(accounts, config.as_ref(), &vault, |config| {
+                config.withdraw_fee(withdraw_amount)
+            })?;
+
+            withdraw_lamports(
+                &mut vault,
+                user_pda_account,
+                user_account,
+                withdraw_amount,
+                fee,
+                &clock,
+            )?;
+
+            solana_program::msg!(
+                "Withdrawal successful. New PDA balance: {}, fee: {} lamports",
+                vault.balance,
+                fee.map_or(0, |(_, fee_amount)| fee_amount),
+            );
+        }
+
+        VaultInstruction::WithdrawTo { amount, .. } => {
+            solana_program::msg!("Processing withdrawal to recipient");
+
+            let mut vault = load_existing_vault(user_account, user_pda_account)?;
+            vault.ensure_single_owner()?;
+            vault.ensure_unlocked(&clock)?;
+
+            let recipient_account = solana_program::account_info::next_account_info(accounts_iter)?;
+
+            if recipient_account.key == user_pda_account.key {
+                return Err(solana_program::program_error::ProgramError::InvalidArgument);
+            }
+
+            validation::assert_writable(recipient_account)?;
+
+            let withdraw_amount = *amount;
+
+            solana_program::msg!(
+                "Lamports to withdraw: {}, recipient: {}",
+                withdraw_amount,
+                recipient_account.key,
+            );
+
+            if withdraw_amount == 0 {
+                return Err(VaultError::ZeroAmount.into());
+            }
+
+            check_destination(
+                program_id,
+                accounts,
+                &vault,
+                user_pda_account,
+                recipient_account.key,
+                &clock,
+            )?;
+
+            let fee = protocol_fee(accounts, config.as_ref(), &vault, |config| {
+                config.withdraw_fee(withdraw_amount)
+            })?;
+
+            withdraw_lamports(
+                &mut vault,
+                user_pda_account,
+                recipient_account,
+                withdraw_amount,
+                fee,
+                &clock,
+            )?;
+
+            solana_program::msg!(
+                "Withdrawal successful. New PDA balance: {}, recipient credited, fee: {} lamports",
+                vault.balance,
+                fee.map_or(0, |(_, fee_amount)| fee_amount),
+            );
+        }
+
+        VaultInstruction::Migrate { .. } => {
+            process_migrate(
+                program_id,
+                user_account,
+                user_pda_account,
+                bump_seed,
+                &clock,
+            )?;
+        }
+
+        VaultInstruction::CreateVault { .. } => {
+            solana_program::msg!("Creating vault");
+
+            // A sponsor paying the rent follows the config PDA; without one
+            // the owner pays.
+            let payer_account = match accounts_iter.next() {
+                Some(payer_account) => payer_account,
+                None => user_account,
+            };
+
+            validation::assert_writable_signer(payer_account)?;
+
+            if !user_pda_account.data_is_empty() {
+                return Err(VaultError::VaultAlreadyExists.into());
+            }
+
+            create_vault(
+                program_id,
+                payer_account,
+                user_account,
+                user_pda_account,
+                bump_seed,
+                vault_name,
+                &clock,
+            )?;
+
+            solana_program::msg!(
+                "Vault created for {}, rent paid by {}",
+                user_account.key,
+                payer_account.key,
+            );
+        }
+
+        VaultInstruction::SetLock {
+            lock_kind,
+            unlock_at,
+            ..
+        } => {
+            solana_program::msg!("Setting vault lock");
+
+            let mut vault = load_existing_vault(user_account, user_pda_account)?;
+
+            let (lock_kind, unlock_at) = (*lock_kind, *unlock_at);
+
+            solana_program::msg!("Lock kind: {:?}, unlock at: {}", lock_kind, unlock_at);
+
+            vault.extend_lock(lock_kind, unlock_at, clock.unix_timestamp)?;
+            vault.pack(&mut user_pda_account.try_borrow_mut_data()?)?;
+
+            solana_program::msg!(
+                "Vault locked until timestamp {} and slot {}",
+                vault.unlock_timestamp,
+                vault.unlock_slot,
+            );
+        }
+
+        VaultInstruction::GrantVesting { amount, terms, .. } => {
+            solana_program::msg!("Granting vesting schedule");
+
+            let grantor_account = solana_program::account_info::next_account_info(accounts_iter)?;
+
+            validation::assert_writable_signer(grantor_account)?;
+
+            if user_pda_account.data_is_empty() {
+                create_vault(
+                    program_id,
+                    grantor_account,
+                    user_account,
+                    user_pda_account,
+                    bump_seed,
+                    vault_name,
+                    &clock,
+                )?;
+            }
+
+            let mut vault = load_existing_vault(user_account, user_pda_account)?;
+
+            if vault.vesting.unvested_amount(clock.unix_timestamp) > 0 {
+                return Err(VaultError::VestingActive.into());
+            }
+
+            let (total_amount, terms) = (*amount, *terms);
+
+            solana_program::msg!(
+                "Grant of {} lamports from {}: start {}, cliff {}, end {}, revocable {}",
+                total_amount,
+                grantor_account.key,
+                terms.start,
+                terms.cliff,
+                terms.end,
+                terms.revocable,
+            );
+
+            let vesting = state::VestingSchedule::new(*grantor_account.key, &terms, total_amount)?;
+
+            solana_program::program::invoke(
+                &solana_program::system_instruction::transfer(
+                    grantor_account.key,
+                    user_pda_account.key,
+                    total_amount,
+                ),
+                &[grantor_account.clone(), user_pda_account.clone()],
+            )?;
+
+            vault.deposit(total_amount, clock.unix_timestamp)?;
+            vault.vesting = vesting;
+            vault.pack(&mut user_pda_account.try_borrow_mut_data()?)?;
+
+            VaultEvent::Deposit(event::Deposit {
+                vault: *user_pda_account.key,
+                depositor: *grantor_account.key,
+                amount: total_amount,
+                fee: 0,
+                balance: vault.balance,
+                timestamp: clock.unix_timestamp,
+            })
+            .emit();
+        }
+
+        VaultInstruction::RevokeVesting { .. } => {
+            solana_program::msg!("Revoking vesting schedule");
+
+            let mut vault = load_existing_vault(user_account, user_pda_account)?;
+
+            let grantor_account = solana_program::account_info::next_account_info(accounts_iter)?;
+
+            validation::assert_writable_signer(grantor_account)?;
+
+            if vault.vesting.total == 0 || vault.vesting.grantor != *grantor_account.key {
+                return Err(VaultError::InvalidVestingGrantor.into());
+            }
+
+            if !vault.vesting.revocable {
+                return Err(VaultError::VestingNotRevocable.into());
+            }
+
+            let unvested_amount = vault.vesting.unvested_amount(clock.unix_timestamp);
+            vault.vesting = state::VestingSchedule::default();
+
+            solana_program::msg!("Returning {} unvested lamports", unvested_amount);
+
+            // The unvested lamports were never the owner's to spend, so
+            // their return bypasses the owner's withdrawal limit.
+            vault.withdraw(unvested_amount, clock.unix_timestamp)?;
+            transfer_lamports(user_pda_account, grantor_account, unvested_amount)?;
+            vault.pack(&mut user_pda_account.try_borrow_mut_data()?)?;
+
+            VaultEvent::Withdraw(event::Withdraw {
+                vault: *user_pda_account.key,
+                destination: *grantor_account.key,
+                amount: unvested_amount,
+                fee: 0,
+                balance: vault.balance,
+                timestamp: clock.unix_timestamp,
+            })
+            .emit();
+        }
+
+        VaultInstruction::OpenStream {
+            amount,
+            stream_id,
+            rate,
+            ..
+        } => {
+            let stream_accounts = StreamAccounts::parse(accounts_iter)?;
+
+            process_open_stream(
+                program_id,
+                accounts,
+                user_account,
+                user_pda_account,
+                &stream_accounts,
+                config.as_ref(),
+                *amount,
+                *stream_id,
+                *rate,
+                &clock,
+            )?;
+        }
+
+        VaultInstruction::ClaimStream { stream_id, .. } => {
+            solana_program::msg!("Claiming stream");
+
+            let stream_accounts = StreamAccounts::parse(accounts_iter)?;
+
+            validation::assert_writable_signer(stream_accounts.recipient_account)?;
+
+            let mut stream =
+                stream_accounts.load(program_id, user_account, user_pda_account, *stream_id)?;
+
+            let claim_amount = stream.claim(clock.unix_timestamp);
+
+            if claim_amount == 0 {
+                return Err(VaultError::ZeroAmount.into());
+            }
+
+            transfer_lamports(
+                stream_accounts.stream_account,
+                stream_accounts.recipient_account,
+                claim_amount,
+            )?;
+
+            stream.pack(&mut stream_accounts.stream_account.try_borrow_mut_data()?)?;
+
+            solana_program::msg!(
+                "Claimed {} lamports, {} of {} streamed so far",
+                claim_amount,
+                stream.withdrawn,
+                stream.deposited,
+            );
+        }
+
+        VaultInstruction::CancelStream { stream_id, .. } => {
+            solana_program::msg!("Cancelling stream");
+
+            let stream_accounts = StreamAccounts::parse(accounts_iter)?;
+
+            if !user_account.is_signer && !stream_accounts.recipient_account.is_signer {
+                return Err(VaultError::MissingSignature.into());
+            }
+
+            validation::assert_writable(user_account)?;
+            validation::assert_writable(stream_accounts.recipient_account)?;
+
+            let mut stream =
+                stream_accounts.load(program_id, user_account, user_pda_account, *stream_id)?;
+
+            let claim_amount = stream.claim(clock.unix_timestamp);
+            let refund_amount = stream.deposited - stream.withdrawn;
+
+            transfer_lamports(
+                stream_accounts.stream_account,
+                stream_accounts.recipient_account,
+                claim_amount,
+            )?;
+
+            // If the vault was closed meanwhile, the refund goes to the sender
+            // together with the stream rent below.
+            if refund_amount > 0 && !user_pda_account.data_is_empty() {
+                transfer_lamports(
+                    stream_accounts.stream_account,
+                    user_pda_account,
+                    refund_amount,
+                )?;
+
+                // The stream was funded from this vault, which may have been
+                // recovered to another authority since.
+                let mut vault = state::Vault::unpack(&user_pda_account.try_borrow_data()?)?;
+                vault.deposit(refund_amount, clock.unix_timestamp)?;
+                vault.pack(&mut user_pda_account.try_borrow_mut_data()?)?;
+
+                VaultEvent::Deposit(event::Deposit {
+                    vault: *user_pda_account.key,
+                    depositor: *stream_accounts.stream_account.key,
+                    amount: refund_amount,
+                    fee: 0,
+                    balance: vault.balance,
+                    timestamp: clock.unix_timestamp,
+                })
+                .emit();
+            }
+
+            let rent_lamports = stream_accounts.stream_account.lamports();
+            transfer_lamports(stream_accounts.stream_account, user_account, rent_lamports)?;
+
+            stream_accounts
+                .stream_account
+                .try_borrow_mut_data()?
+                .fill(0);
+            stream_accounts.stream_account.realloc(0, false)?;
+            stream_accounts
+                .stream_account
+                .assign(&solana_program::system_program::ID);
+
+            solana_program::msg!(
+                "Stream cancelled. Recipient paid: {}, refunded to vault: {}",
+                claim_amount,
+                refund_amount,
+            );
+        }
+
+        VaultInstruction::EnableMultisig {
+            threshold, owners, ..
+        } => {
+            solana_program::msg!("Enabling multisig");
+
+            let mut vault = load_existing_vault(user_account, user_pda_account)?;
+            vault.ensure_single_owner()?;
+
+            // Proposals only move lamports; the tokens have to leave first.
+            if vault.token_balances > 0 {
+                return Err(VaultError::TokensHeld.into());
+            }
+
+            let multisig_account = solana_program::account_info::next_account_info(accounts_iter)?;
+            validation::assert_writable(user_account)?;
+            validation::assert_writable(multisig_account)?;
+
+            let (multisig_address, multisig_bump) =
+                state::Multisig::find_address(program_id, user_pda_account.key);
+
+            if multisig_account.key != &multisig_address {
+                return Err(VaultError::InvalidMultisigAddress.into());
+            }
+
+            let multisig =
+                state::Multisig::new(*user_pda_account.key, multisig_bump, *threshold, owners)?;
+
+            let rent = solana_program::rent::Rent::get()?;
+
+            solana_program::program::invoke_signed(
+                &solana_program::system_instruction::create_account(
+                    user_account.key,
+                    multisig_account.key,
+                    rent.minimum_balance(state::Multisig::LEN),
+                    state::Multisig::LEN as u64,
+                    program_id,
+                ),
+                &[user_account.clone(), multisig_account.clone()],
+                &[&[
+                    state::MULTISIG_SEED,
+                    user_pda_account.key.as_ref(),
+                    &[multisig_bump],
+                ]],
+            )?;
+
+            multisig.pack(&mut multisig_account.try_borrow_mut_data()?)?;
+
+            vault.multisig = true;
+            vault.updated_at = clock.unix_timestamp;
+            vault.pack(&mut user_pda_account.try_borrow_mut_data()?)?;
+
+            solana_program::msg!(
+                "Multisig enabled: {} of {} owners",
+                multisig.threshold,
+                multisig.owner_count,
+            );
+        }
+
+        VaultInstruction::Propose {
+            amount, expires_at, ..
+        } => {
+            solana_program::msg!("Proposing multisig withdrawal");
+
+            load_existing_vault(user_account, user_pda_account)?;
+
+            let multisig_accounts = MultisigAccounts::parse(accounts_iter)?;
+            let destination_account =
+                solana_program::account_info::next_account_info(accounts_iter)?;
+
+            validation::assert_writable(multisig_accounts.multisig_account)?;
+            validation::assert_writable(multisig_accounts.owner_account)?;
+
+            let mut multisig = multisig_accounts.load_multisig(program_id, user_pda_account)?;
+            let owner_index = multisig.owner_index(multisig_accounts.owner_account.key)?;
+
+            let withdraw_amount = *amount;
+            let expires_at = *expires_at;
+            let proposal_id = multisig.proposal_count;
+
+            solana_program::msg!(
+                "Proposal {}: {} lamports to {}, expires at {}",
+                proposal_id,
+                withdraw_amount,
+                destination_account.key,
+                expires_at,
+            );
+
+            if withdraw_amount == 0 {
+                return Err(VaultError::ZeroAmount.into());
+            }
+
+            if expires_at <= clock.unix_timestamp {
+                return Err(VaultError::ProposalExpired.into());
+            }
+
+            let (proposal_address, proposal_bump) =
+                state::Proposal::find_address(program_id, user_pda_account.key, proposal_id);
+
+            if multisig_accounts.proposal_account.key != &proposal_address {
+                return Err(VaultError::InvalidProposalAddress.into());
+            }
+
+            let rent = solana_program::rent::Rent::get()?;
+
+            solana_program::program::invoke_signed(
+                &solana_program::system_instruction::create_account(
+                    multisig_accounts.owner_account.key,
+                    multisig_accounts.proposal_account.key,
+                    rent.minimum_balance(state::Proposal::LEN),
+                    state::Proposal::LEN as u64,
+                    program_id,
+                ),
+                &[
+                    multisig_accounts.owner_account.clone(),
+                    multisig_accounts.proposal_account.clone(),
+                ],
+                &[&[
+                    state::PROPOSAL_SEED,
+                    user_pda_account.key.as_ref(),
+                    &proposal_id.to_le_bytes(),
+                    &[proposal_bump],
+                ]],
+            )?;
+
+            let mut proposal = state::Proposal::new(
+                *user_pda_account.key,
+                proposal_id,
+                proposal_bump,
+                *multisig_accounts.owner_account.key,
+                *destination_account.key,
+                withdraw_amount,
+                expires_at,
+                clock.unix_timestamp,
+            );
+            proposal.approve(owner_index, clock.unix_timestamp);
+            proposal.pack(&mut multisig_accounts.proposal_account.try_borrow_mut_data()?)?;
+
+            multisig.proposal_count += 1;
+            multisig.pack(&mut multisig_accounts.multisig_account.try_borrow_mut_data()?)?;
+        }
+
+        VaultInstruction::Approve { proposal_id, .. } => {
+            solana_program::msg!("Approving multisig proposal");
+
+            load_existing_vault(user_account, user_pda_account)?;
+
+            let multisig_accounts = MultisigAccounts::parse(accounts_iter)?;
+
+            let multisig = multisig_accounts.load_multisig(program_id, user_pda_account)?;
+            let owner_index = multisig.owner_index(multisig_accounts.owner_account.key)?;
+
+            let mut proposal =
+                multisig_accounts.load_proposal(program_id, user_pda_account, *proposal_id)?;
+            proposal.ensure_pending(clock.unix_timestamp)?;
+            proposal.approve(owner_index, clock.unix_timestamp);
+            proposal.pack(&mut multisig_accounts.proposal_account.try_borrow_mut_data()?)?;
+
+            solana_program::msg!(
+                "Proposal {} approvals: {} of {} required",
+                proposal.proposal_id,
+                proposal.approval_count(),
+                multisig.threshold,
+            );
+        }
+
+        VaultInstruction::Execute { proposal_id, .. } => {
+            solana_program::msg!("Executing multisig proposal");
+
+            let mut vault = load_existing_vault(user_account, user_pda_account)?;
+            vault.ensure_unlocked(&clock)?;
+
+            let multisig_accounts = MultisigAccounts::parse(accounts_iter)?;
+            let destination_account =
+                solana_program::account_info::next_account_info(accounts_iter)?;
+
+            validation::assert_writable(destination_account)?;
+
+            let multisig = multisig_accounts.load_multisig(program_id, user_pda_account)?;
+            multisig.owner_index(multisig_accounts.owner_account.key)?;
+
+            let mut proposal =
+                multisig_accounts.load_proposal(program_id, user_pda_account, *proposal_id)?;
+            proposal.ensure_pending(clock.unix_timestamp)?;
+
+            if proposal.approval_count() < multisig.threshold as u32 {
+                return Err(VaultError::InsufficientApprovals.into());
+            }
+
+            if destination_account.key != &proposal.destination {
+                return Err(solana_program::program_error::ProgramError::InvalidArgument);
+            }
+
+            proposal.executed = true;
+            proposal.updated_at = clock.unix_timestamp;
+            proposal.pack(&mut multisig_accounts.proposal_account.try_borrow_mut_data()?)?;
+
+            check_destination(
+                program_id,
+                accounts,
+                &vault,
+                user_pda_account,
+                destination_account.key,
+                &clock,
+            )?;
+
+            let fee = protocol_fee(accounts, config.as_ref(), &vault, |config| {
+                config.withdraw_fee(proposal.amount)
+            })?;
+
+            withdraw_lamports(
+                &mut vault,
+                user_pda_account,
+                destination_account,
+                proposal.amount,
+                fee,
+                &clock,
+            )?;
+
+            solana_program::msg!(
+                "Proposal {} executed. {} lamports sent to {}",
+                proposal.proposal_id,
+                proposal.amount,
+                destination_account.key,
+            );
+        }
+
+        VaultInstruction::ApproveDelegate {
+            amount,
+            expiry_slot,
+            ..
+        } => {
+            solana_program::msg!("Approving delegate");
+
+            let vault = load_existing_vault(user_account, user_pda_account)?;
+            vault.ensure_single_owner()?;
+
+            let delegate_account = solana_program::account_info::next_account_info(accounts_iter)?;
+            let allowance_account = solana_program::account_info::next_account_info(accounts_iter)?;
+
+            let allowance_bump = check_allowance_address(
+                program_id,
+                user_pda_account,
+                delegate_account,
+                allowance_account,
+            )?;
+
+            validation::assert_writable(user_account)?;
+            validation::assert_writable(allowance_account)?;
+            validation::assert_owned_or_uninitialized(allowance_account, program_id)?;
+
+            if allowance_account.data_is_empty() {
+                let rent = solana_program::rent::Rent::get()?;
+
+                solana_program::program::invoke_signed(
+                    &solana_program::system_instruction::create_account(
+                        user_account.key,
+                        allowance_account.key,
+                        rent.minimum_balance(state::Allowance::LEN),
+                        state::Allowance::LEN as u64,
+                        program_id,
+                    ),
+                    &[user_account.clone(), allowance_account.clone()],
+                    &[&[
+                        state::ALLOWANCE_SEED,
+                        user_pda_account.key.as_ref(),
+                        delegate_account.key.as_ref(),
+                        &[allowance_bump],
+                    ]],
+                )?;
+
+                state::Allowance::new(
+                    *user_pda_account.key,
+                    *delegate_account.key,
+                    allowance_bump,
+                    vault.created_slot,
+                    clock.unix_timestamp,
+                )
+                .pack(&mut allowance_account.try_borrow_mut_data()?)?;
+            }
+
+            let mut allowance = state::Allowance::unpack(&allowance_account.try_borrow_data()?)?;
+
+            // An allowance left behind by a closed vault starts over.
+            if allowance.vault_created_slot != vault.created_slot {
+                allowance = state::Allowance::new(
+                    *user_pda_account.key,
+                    *delegate_account.key,
+                    allowance_bump,
+                    vault.created_slot,
+                    clock.unix_timestamp,
+                );
+            }
+
+            allowance.amount = *amount;
+            allowance.expiry_slot = *expiry_slot;
+            allowance.updated_at = clock.unix_timestamp;
+            allowance.pack(&mut allowance_account.try_borrow_mut_data()?)?;
+
+            solana_program::msg!(
+                "Delegate {} may withdraw {} lamports, expiry slot: {}",
+                delegate_account.key,
+                allowance.amount,
+                allowance.expiry_slot,
+            );
+        }
+
+        VaultInstruction::RevokeDelegate { .. } => {
+            solana_program::msg!("Revoking delegate");
+
+            load_existing_vault(user_account, user_pda_account)?;
+
+            let delegate_account = solana_program::account_info::next_account_info(accounts_iter)?;
+            let allowance_account = solana_program::account_info::next_account_info(accounts_iter)?;
+
+            check_allowance_address(
+                program_id,
+                user_pda_account,
+                delegate_account,
+                allowance_account,
+            )?;
+
+            validation::assert_writable(user_account)?;
+            validation::assert_state_account(allowance_account, program_id)?;
+
+            state::Allowance::unpack(&allowance_account.try_borrow_data()?)?;
+
+            let rent_lamports = allowance_account.lamports();
+            transfer_lamports(allowance_account, user_account, rent_lamports)?;
+
+            allowance_account.try_borrow_mut_data()?.fill(0);
+            allowance_account.realloc(0, false)?;
+            allowance_account.assign(&solana_program::system_program::ID);
+
+            solana_program::msg!("Delegate {} revoked", delegate_account.key);
+        }
+
+        VaultInstruction::SetWithdrawalLimit { limit, .. } => {
+            solana_program::msg!("Setting withdrawal limit");
+
+            let mut vault = load_existing_vault(user_account, user_pda_account)?;
+            vault.ensure_single_owner()?;
+
+            let limit = *limit;
+
+            vault.withdrawal_limit.set(limit, clock.unix_timestamp);
+            vault.updated_at = clock.unix_timestamp;
+            vault.pack(&mut user_pda_account.try_borrow_mut_data()?)?;
+
+            solana_program::msg!(
+                "Withdrawal limit: {} lamports per window, pending: {} at {}",
+                vault.withdrawal_limit.limit,
+                vault.withdrawal_limit.pending_limit,
+                vault.withdrawal_limit.pending_at,
+            );
+        }
+
+        VaultInstruction::AddDestination { .. } | VaultInstruction::RemoveDestination { .. } => {
+            solana_program::msg!("Updating allowlist");
+
+            let vault = load_existing_vault(user_account, user_pda_account)?;
+            vault.ensure_single_owner()?;
+
+            let allowlist_account = solana_program::account_info::next_account_info(accounts_iter)?;
+            let destination_account =
+                solana_program::account_info::next_account_info(accounts_iter)?;
+
+            let (allowlist_address, allowlist_bump) =
+                state::Allowlist::find_address(program_id, user_pda_account.key);
+
+            if allowlist_account.key != &allowlist_address {
+                return Err(VaultError::InvalidAllowlistAddress.into());
+            }
+
+            validation::assert_writable(user_account)?;
+            validation::assert_writable(allowlist_account)?;
+            validation::assert_owned_or_uninitialized(allowlist_account, program_id)?;
+
+            if allowlist_account.data_is_empty() {
+                let rent = solana_program::rent::Rent::get()?;
+
+                solana_program::program::invoke_signed(
+                    &solana_program::system_instruction::create_account(
+                        user_account.key,
+                        allowlist_account.key,
+                        rent.minimum_balance(state::Allowlist::LEN),
+                        state::Allowlist::LEN as u64,
+                        program_id,
+                    ),
+                    &[user_account.clone(), allowlist_account.clone()],
+                    &[&[
+                        state::ALLOWLIST_SEED,
+                        user_pda_account.key.as_ref(),
+                        &[allowlist_bump],
+                    ]],
+                )?;
+
+                state::Allowlist::new(*user_pda_account.key, allowlist_bump, vault.created_slot)
+                    .pack(&mut allowlist_account.try_borrow_mut_data()?)?;
+            }
+
+            let mut allowlist = state::Allowlist::unpack(&allowlist_account.try_borrow_data()?)?;
+
+            // An allowlist left behind by a closed vault starts over.
+            if allowlist.vault_created_slot != vault.created_slot {
+                allowlist = state::Allowlist::new(
+                    *user_pda_account.key,
+                    allowlist_bump,
+                    vault.created_slot,
+                );
+            }
+
+            if matches!(instruction, VaultInstruction::AddDestination { .. }) {
+                // While the allowlist is enforced, a new destination only
+                // becomes usable after the cooldown.
+                let active_at = match vault.allowlist_active(clock.unix_timestamp) {
+                    true => clock.unix_timestamp + state::ALLOWLIST_COOLDOWN,
+                    false => clock.unix_timestamp,
+                };
+
+                allowlist.add(*destination_account.key, active_at)?;
+
+                solana_program::msg!(
+                    "Destination {} allowed from {}",
+                    destination_account.key,
+                    active_at,
+                );
+            } else {
+                allowlist.remove(destination_account.key)?;
+
+                solana_program::msg!("Destination {} removed", destination_account.key);
+            }
+
+            allowlist.pack(&mut allowlist_account.try_borrow_mut_data()?)?;
+        }
+
+        VaultInstruction::SetAllowlist { enabled, .. } => {
+            solana_program::msg!("Setting allowlist");
+
+            let mut vault = load_existing_vault(user_account, user_pda_account)?;
+            vault.ensure_single_owner()?;
+
+            vault.set_allowlist(*enabled, clock.unix_timestamp);
+            vault.pack(&mut user_pda_account.try_borrow_mut_data()?)?;
+
+            solana_program::msg!(
+                "Allowlist enabled: {}, disabled at: {}",
+                vault.allowlist_enabled,
+                vault.allowlist_disable_at,
+            );
+        }
+
+        VaultInstruction::SetGuardians {
+            threshold,
+            guardians,
+            ..
+        } => {
+            solana_program::msg!("Setting guardians");
+
+            let vault = load_existing_vault(user_account, user_pda_account)?;
+
+            let guardians_account = solana_program::account_info::next_account_info(accounts_iter)?;
+
+            let guardians_bump =
+                check_guardians_address(program_id, user_pda_account, guardians_account)?;
+
+            validation::assert_writable(user_account)?;
+            validation::assert_writable(guardians_account)?;
+            validation::assert_owned_or_uninitialized(guardians_account, program_id)?;
+
+            let guardians = state::Guardians::new(
+                *user_pda_account.key,
+                guardians_bump,
+                vault.created_slot,
+                *threshold,
+                guardians,
+            )?;
+
+            if guardians_account.data_is_empty() {
+                let rent = solana_program::rent::Rent::get()?;
+
+                solana_program::program::invoke_signed(
+                    &solana_program::system_instruction::create_account(
+                        user_account.key,
+                        guardians_account.key,
+                        rent.minimum_balance(state::Guardians::LEN),
+                        state::Guardians::LEN as u64,
+                        program_id,
+                    ),
+                    &[user_account.clone(), guardians_account.clone()],
+                    &[&[
+                        state::GUARDIANS_SEED,
+                        user_pda_account.key.as_ref(),
+                        &[guardians_bump],
+                    ]],
+                )?;
+            } else {
+                // Replacing the guardians also drops any pending recovery.
+                state::Guardians::unpack(&guardians_account.try_borrow_data()?)?;
+            }
+
+            guardians.pack(&mut guardians_account.try_borrow_mut_data()?)?;
+
+            solana_program::msg!(
+                "Guardians set: {} of {}",
+                guardians.threshold,
+                guardians.guardian_count,
+            );
+        }
+
+        VaultInstruction::ApproveRecovery { .. } => {
+            solana_program::msg!("Approving recovery");
+
+            let vault = state::Vault::unpack(&user_pda_account.try_borrow_data()?)?;
+
+            let guardians_account = solana_program::account_info::next_account_info(accounts_iter)?;
+            let guardian_account = solana_program::account_info::next_account_info(accounts_iter)?;
+            let new_authority_account =
+                solana_program::account_info::next_account_info(accounts_iter)?;
+
+            validation::assert_signer(guardian_account)?;
+
+            let mut guardians =
+                load_guardians(program_id, &vault, user_pda_account, guardians_account)?;
+            let guardian_index = guardians.guardian_index(guardian_account.key)?;
+
+            guardians.approve(
+                guardian_index,
+                *new_authority_account.key,
+                clock.unix_timestamp,
+            )?;
+            guardians.pack(&mut guardians_account.try_borrow_mut_data()?)?;
+
+            solana_program::msg!(
+                "Recovery to {}: {} of {} approvals",
+                new_authority_account.key,
+                guardians.approval_count(new_authority_account.key),
+                guardians.threshold,
+            );
+
+            if guardians.new_authority != solana_program::pubkey::Pubkey::default() {
+                solana_program::msg!(
+                    "Recovery to {} ready at {}",
+                    guardians.new_authority,
+                    guardians.ready_at,
+                );
+            }
+        }
+
+        VaultInstruction::ExecuteRecovery { .. } => {
+            solana_program::msg!("Executing recovery");
+
+            let mut vault = state::Vault::unpack(&user_pda_account.try_borrow_data()?)?;
+
+            let guardians_account = solana_program::account_info::next_account_info(accounts_iter)?;
+            let new_authority_account =
+                solana_program::account_info::next_account_info(accounts_iter)?;
+
+            let mut guardians =
+                load_guardians(program_id, &vault, user_pda_account, guardians_account)?;
+
+            if guardians.ready_authority(clock.unix_timestamp)? != *new_authority_account.key {
+                return Err(solana_program::program_error::ProgramError::InvalidArgument);
+            }
+
+            // The new key proves it can sign before it takes over.
+            validation::assert_signer(new_authority_account)?;
+
+            solana_program::msg!(
+                "Vault authority rotated from {} to {}",
+                vault.authority(),
+                new_authority_account.key,
+            );
+
+            vault.authority = *new_authority_account.key;
+            vault.updated_at = clock.unix_timestamp;
+            vault.pack(&mut user_pda_account.try_borrow_mut_data()?)?;
+
+            guardians.cancel();
+            guardians.pack(&mut guardians_account.try_borrow_mut_data()?)?;
+        }
+
+        VaultInstruction::CancelRecovery { .. } => {
+            solana_program::msg!("Cancelling recovery");
+
+            let vault = load_existing_vault(user_account, user_pda_account)?;
+
+            let guardians_account = solana_program::account_info::next_account_info(accounts_iter)?;
+
+            let mut guardians =
+                load_guardians(program_id, &vault, user_pda_account, guardians_account)?;
+
+            if guardians.new_authority == solana_program::pubkey::Pubkey::default() {
+                return Err(VaultError::NoPendingRecovery.into());
+            }
+
+            guardians.cancel();
+            guardians.pack(&mut guardians_account.try_borrow_mut_data()?)?;
+        }
+
+        VaultInstruction::InitializeConfig { .. }
+        | VaultInstruction::SetPaused { .. }
+        | VaultInstruction::UpdateConfig { .. }
+        | VaultInstruction::SetFeeExempt { .. } => unreachable!("config instructions return early"),
+
+        VaultInstruction::Sync { .. } => {
+            solana_program::msg!("Syncing vault balance");
+
+            let mut vault = load_existing_vault(user_account, user_pda_account)?;
+
+            let actual_balance = available_lamports(user_pda_account)?;
+
+            if actual_balance == vault.balance {
+                solana_program::msg!("Balance in sync: {} lamports", vault.balance);
+            } else {
+                solana_program::msg!(
+                    "Balance discrepancy: stored {}, actual {}, difference {}",
+                    vault.balance,
+                    actual_balance,
+                    actual_balance as i128 - vault.balance as i128,
+                );
+
+                let previous_balance = vault.balance;
+
+                vault.sync(actual_balance, clock.unix_timestamp)?;
+                vault.pack(&mut user_pda_account.try_borrow_mut_data()?)?;
+
+                VaultEvent::BalanceAdjusted(event::BalanceAdjusted {
+                    vault: *user_pda_account.key,
+                    mint: solana_program::pubkey::Pubkey::default(),
+                    previous_balance,
+                    balance: vault.balance,
+                    timestamp: clock.unix_timestamp,
+                })
+                .emit();
+            }
+        }
+
+        VaultInstruction::Close { .. } => {
+            solana_program::msg!("Closing vault");
+
+            let mut vault = load_existing_vault(user_account, user_pda_account)?;
+            vault.ensure_single_owner()?;
+            vault.ensure_unlocked(&clock)?;
+
+            if vault.vesting.unvested_amount(clock.unix_timestamp) > 0 {
+                return Err(VaultError::VestingActive.into());
+            }
+
+            vault
+                .withdrawal_limit
+                .consume(vault.balance, clock.unix_timestamp)?;
+
+            let destination_account =
+                solana_program::account_info::next_account_info(accounts_iter)?;
+
+            if destination_account.key == user_pda_account.key {
+                return Err(solana_program::program_error::ProgramError::InvalidArgument);
+            }
+
+            validation::assert_writable(destination_account)?;
+
+            check_destination(
+                program_id,
+                accounts,
+                &vault,
+                user_pda_account,
+                destination_account.key,
+                &clock,
+            )?;
+
+            let fee = protocol_fee(accounts, config.as_ref(), &vault, |config| {
+                config.withdraw_fee(vault.balance)
+            })?;
+            let fee_amount = fee.map_or(0, |(_, fee_amount)| fee_amount);
+
+            if let Some((treasury_account, fee_amount)) = fee {
+                transfer_lamports(user_pda_account, treasury_account, fee_amount)?;
+            }
+
+            let vault_lamports = user_pda_account.lamports();
+
+            transfer_lamports(user_pda_account, destination_account, vault_lamports)?;
+
+            user_pda_account.try_borrow_mut_data()?.fill(0);
+            user_pda_account.realloc(0, false)?;
+            user_pda_account.assign(&solana_program::system_program::ID);
+
+            VaultEvent::Withdraw(event::Withdraw {
+                vault: *user_pda_account.key,
+                destination: *destination_account.key,
+                amount: vault.balance,
+                fee: fee_amount,
+                balance: 0,
+                timestamp: clock.unix_timestamp,
+            })
+            .emit();
+
+            solana_program::msg!(
+                "Vault closed. Balance: {}, lamports sent to {}: {}",
+                vault.balance,
+                destination_account.key,
+                vault_lamports,
+            );
+        }
+
+        VaultInstruction::DepositToken { amount, .. } => {
+            solana_program::msg!("Processing token deposit");
+
+            validation::assert_writable(user_account)?;
+
+            let mut vault = load_vault(
+                program_id,
+                user_account,
+                user_pda_account,
+                bump_seed,
+                vault_name,
+                &clock,
+            )?;
+
+            // Proposals only move lamports, so tokens deposited into a
+            // multisig vault could never leave it.
+            vault.ensure_single_owner()?;
+
+            let deposit_amount = *amount;
+
+            solana_program::msg!("Tokens to deposit: {}", deposit_amount);
+
+            if deposit_amount == 0 {
+                return Err(VaultError::ZeroAmount.into());
+            }
+
+            let token_accounts =
+                TokenAccounts::parse(accounts_iter, system_program, user_pda_account)?;
+            let decimals = token_accounts.checked_mint_decimals()?;
+
+            if token_accounts.vault_token_account.data_is_empty() {
+                solana_program::msg!("Creating vault token account");
+
+                solana_program::program::invoke(
+                    &spl_associated_token_account::instruction::create_associated_token_account_idempotent(
+                        user_account.key,
+                        user_pda_account.key,
+                        token_accounts.mint_account.key,
+                        token_accounts.token_program.key,
+                    ),
+                    &[
+                        user_account.clone(),
+                        token_accounts.vault_token_account.clone(),
+                        user_pda_account.clone(),
+                        token_accounts.mint_account.clone(),
+                        token_accounts.system_program.clone(),
+                        token_accounts.token_program.clone(),
+                        token_accounts.associated_token_program.clone(),
+                    ],
+                )?;
+            }
+
+            let mut token_balance = load_token_balance(
+                program_id,
+                user_account,
+                user_pda_account,
+                &token_accounts,
+                &clock,
+                true,
+            )?;
+
+            let balance_before = token_accounts.vault_token_amount()?;
+
+            spl_token_2022::onchain::invoke_transfer_checked(
+                token_accounts.token_program.key,
+                token_accounts.user_token_account.clone(),
+                token_accounts.mint_account.clone(),
+                token_accounts.vault_token_account.clone(),
+                user_account.clone(),
+                token_accounts.remaining_accounts,
+                deposit_amount,
+                decimals,
+                &[],
+            )?;
+
+            let received_amount = token_accounts
+                .vault_token_amount()?
+                .checked_sub(balance_before)
+                .ok_or(VaultError::InvalidTokenAccount)?;
+
+            solana_program::msg!("Tokens received after fees: {}", received_amount);
+
+            if received_amount == 0 {
+                return Err(VaultError::ZeroAmount.into());
+            }
+
+            let previous_balance = token_balance.balance;
+
+            token_balance.deposit(received_amount, clock.unix_timestamp)?;
+            token_balance.pack(&mut token_accounts.token_balance_account.try_borrow_mut_data()?)?;
+
+            if previous_balance == 0 {
+                vault.token_balances = vault
+                    .token_balances
+                    .checked_add(1)
+                    .ok_or(VaultError::BalanceOverflow)?;
+                vault.pack(&mut user_pda_account.try_borrow_mut_data()?)?;
+            }
+
+            VaultEvent::BalanceAdjusted(event::BalanceAdjusted {
+                vault: *user_pda_account.key,
+                mint: token_balance.mint,
+                previous_balance,
+                balance: token_balance.balance,
+                timestamp: clock.unix_timestamp,
+            })
+            .emit();
+
+            solana_program::msg!(
+                "Token deposit successful. Mint: {}, new vault token balance: {}",
+                token_balance.mint,
+                token_balance.balance,
+            );
+        }
+
+        VaultInstruction::WithdrawToken { amount, .. } => {
+            solana_program::msg!("Processing token withdrawal");
+
+            let mut vault = load_vault(
+                program_id,
+                user_account,
+                user_pda_account,
+                bump_seed,
+                vault_name,
+                &clock,
+            )?;
+
+            vault.ensure_single_owner()?;
+            vault.ensure_unlocked(&clock)?;
+
+            let withdraw_amount = *amount;
+
+            solana_program::msg!("Tokens to withdraw: {}", withdraw_amount);
+
+            if withdraw_amount == 0 {
+                return Err(VaultError::ZeroAmount.into());
+            }
+
+            let token_accounts =
+                TokenAccounts::parse(accounts_iter, system_program, user_pda_account)?;
+
+            let mut token_balance = load_token_balance(
+                program_id,
+                user_account,
+                user_pda_account,
+                &token_accounts,
+                &clock,
+                false,
+            )?;
+
+            check_destination(
+                program_id,
+                accounts,
+                &vault,
+                user_pda_account,
+                &token_accounts.user_token_owner()?,
+                &clock,
+            )?;
+
+            let previous_balance = token_balance.balance;
+
+            token_balance.withdraw(withdraw_amount, clock.unix_timestamp)?;
+
+            spl_token_2022::onchain::invoke_transfer_checked(
+                token_accounts.token_program.key,
+                token_accounts.vault_token_account.clone(),
+                token_accounts.mint_account.clone(),
+                token_accounts.user_token_account.clone(),
+                user_pda_account.clone(),
+                token_accounts.remaining_accounts,
+                withdraw_amount,
+                token_accounts.checked_mint_decimals()?,
+                &[&signer_seeds(&vault.owner, vault.name(), &[vault.bump])],
+            )?;
+
+            token_balance.pack(&mut token_accounts.token_balance_account.try_borrow_mut_data()?)?;
+
+            // Saturating, as vaults migrated from older layouts did not count
+            // the balances they already held.
+            if token_balance.balance == 0 {
+                vault.token_balances = vault.token_balances.saturating_sub(1);
+                vault.pack(&mut user_pda_account.try_borrow_mut_data()?)?;
+            }
+
+            VaultEvent::BalanceAdjusted(event::BalanceAdjusted {
+                vault: *user_pda_account.key,
+                mint: token_balance.mint,
+                previous_balance,
+                balance: token_balance.balance,
+                timestamp: clock.unix_timestamp,
+            })
+            .emit();
+
+            solana_program::msg!(
+                "Token withdrawal successful. Mint: {}, new vault token balance: {}",
+                token_balance.mint,
+                token_balance.balance,
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Loads the caller's vault, creating it first if the PDA is still empty.
+fn load_vault<'a>(
+    program_id: &solana_program::pubkey::Pubkey,
+    user_account: &solana_program::account_info::AccountInfo<'a>,
+    user_pda_account: &solana_program::account_info::AccountInfo<'a>,
+    bump_seed: u8,
+    vault_name: &[u8],
+    clock: &solana_program::clock::Clock,
+) -> Result<state::Vault, solana_program::program_error::ProgramError> {
+    solana_program::msg!("Checking PDA data");
+
+    if user_pda_account.data_is_empty() {
+        solana_program::msg!("PDA data empty");
+
+        create_vault(
+            program_id,
+            user_account,
+            user_account,
+            user_pda_account,
+            bump_seed,
+            vault_name,
+            clock,
+        )?;
+    }
+
+    load_existing_vault(user_account, user_pda_account)
+}
+
+/// Creates and initializes the vault PDA for `user_account`, with rent
+/// funded by `payer_account`.
+fn create_vault<'a>(
+    program_id: &solana_program::pubkey::Pubkey,
+    payer_account: &solana_program::account_info::AccountInfo<'a>,
+    user_account: &solana_program::account_info::AccountInfo<'a>,
+    user_pda_account: &solana_program::account_info::AccountInfo<'a>,
+    bump_seed: u8,
+    vault_name: &[u8],
+    clock: &solana_program::clock::Clock,
+) -> solana_program::entrypoint::ProgramResult {
+    validation::assert_writable_signer(payer_account)?;
+    validation::assert_writable(user_pda_account)?;
+
+    let rent = solana_program::rent::Rent::get()?;
+    let rent_required_lamports = rent.minimum_balance(state::Vault::LEN);
+
+    if **payer_account.try_borrow_lamports()? <= rent_required_lamports {
+        return Err(VaultError::InsufficientFundsForRent.into());
+    }
+
+    solana_program::program::invoke_signed(
+        &solana_program::system_instruction::create_account(
+            payer_account.key,
+            user_pda_account.key,
+            rent_required_lamports,
+            state::Vault::LEN as u64,
+            program_id,
+        ),
+        &[payer_account.clone(), user_pda_account.clone()],
+        &[&signer_seeds(user_account.key, vault_name, &[bump_seed])],
+    )?;
+
+    state::Vault {
+        created_slot: clock.slot,
+        ..state::Vault::new(
+            *user_account.key,
+            bump_seed,
+            vault_name,
+            clock.unix_timestamp,
+        )
+    }
+    .pack(&mut user_pda_account.try_borrow_mut_data()?)?;
+
+    VaultEvent::VaultCreated(event::VaultCreated {
+        vault: *user_pda_account.key,
+        owner: *user_account.key,
+        payer: *payer_account.key,
+        name: vault_name.to_vec(),
+        timestamp: clock.unix_timestamp,
+    })
+    .emit();
+
+    Ok(())
+}
+
+/// Moves lamports out of the vault, never below its rent-exempt minimum,
+/// into an unvested grant or past the withdrawal limit, and records the
+/// withdrawal. A protocol fee is carved out of `amount` for the treasury.
+fn withdraw_lamports(
+    vault: &mut state::Vault,
+    user_pda_account: &solana_program::account_info::AccountInfo,
+    recipient_account: &solana_program::account_info::AccountInfo,
+    amount: u64,
+    fee: Option<(&solana_program::account_info::AccountInfo, u64)>,
+    clock: &solana_program::clock::Clock,
+) -> solana_program::entrypoint::ProgramResult {
+    validation::assert_writable(recipient_account)?;
+
+    let available_lamports = available_lamports(user_pda_account)?;
+
+    if amount > available_lamports {
+        solana_program::msg!("Lamports available above rent: {}", available_lamports);
+
+        return Err(VaultError::ExceedsAvailableLamports.into());
+    }
+
+    let unvested_amount = vault.vesting.unvested_amount(clock.unix_timestamp);
+
+    if amount > vault.balance.saturating_sub(unvested_amount) {
+        solana_program::msg!("Unvested lamports: {}", unvested_amount);
+
+        return Err(VaultError::InsufficientVestedBalance.into());
+    }
+
+    vault
+        .withdrawal_limit
+        .consume(amount, clock.unix_timestamp)?;
+
+    vault.withdraw(amount, clock.unix_timestamp)?;
+
+    let fee_amount = fee.map_or(0, |(_, fee_amount)| fee_amount);
+
+    transfer_lamports(user_pda_account, recipient_account, amount - fee_amount)?;
+
+    if let Some((treasury_account, fee_amount)) = fee {
+        transfer_lamports(user_pda_account, treasury_account, fee_amount)?;
+    }
+
+    VaultEvent::Withdraw(event::Withdraw {
+        vault: *user_pda_account.key,
+        destination: *recipient_account.key,
+        amount,
+        fee: fee_amount,
+        balance: vault.balance,
+        timestamp: clock.unix_timestamp,
+    })
+    .emit();
+
+    vault.pack(&mut user_pda_account.try_borrow_mut_data()?)
+}
+
+/// Lamports the vault holds above its rent-exempt minimum.
+fn available_lamports(
+    user_pda_account: &solana_program::account_info::AccountInfo,
+) -> Result<u64, solana_program::program_error::ProgramError> {
+    let rent = solana_program::rent::Rent::get()?;
+
+    Ok(user_pda_account
+        .lamports()
+        .saturating_sub(rent.minimum_balance(user_pda_account.data_len())))
+}
+
+fn transfer_lamports(
+    from_account: &solana_program::account_info::AccountInfo,
+    to_account: &solana_program::account_info::AccountInfo,
+    amount: u64,
+) -> solana_program::entrypoint::ProgramResult {
+    let from_lamports = from_account
+        .lamports()
+        .checked_sub(amount)
+        .ok_or(VaultError::ExceedsAvailableLamports)?;
+    let to_lamports = to_account
+        .lamports()
+        .checked_add(amount)
+        .ok_or(VaultError::BalanceOverflow)?;
+
+    **from_account.try_borrow_mut_lamports()? = from_lamports;
+    **to_account.try_borrow_mut_lamports()? = to_lamports;
+
+    Ok(())
+}
+
+/// Fails if the vault's allowlist is active and does not allow
+/// `destination`.
+fn check_destination(
+    program_id: &solana_program::pubkey::Pubkey,
+    accounts: &[solana_program::account_info::AccountInfo],
+    vault: &state::Vault,
+    user_pda_account: &solana_program::account_info::AccountInfo,
+    destination: &solana_program::pubkey::Pubkey,
+    clock: &solana_program::clock::Clock,
+) -> solana_program::entrypoint::ProgramResult {
+    if !vault.allowlist_active(clock.unix_timestamp) {
+        return Ok(());
+    }
+
+    let (allowlist_address, _) = state::Allowlist::find_address(program_id, user_pda_account.key);
+    let allowlist_account = find_account(accounts, &allowlist_address)
+        .filter(|account| account.owner == program_id)
+        .ok_or(VaultError::InvalidAllowlistAddress)?;
+
+    let allowlist = state::Allowlist::unpack(&allowlist_account.try_borrow_data()?)?;
+    vault.ensure_companion(allowlist.vault_created_slot)?;
+
+    if !allowlist.is_allowed(destination, clock.unix_timestamp) {
+        solana_program::msg!("Destination not allowed: {}", destination);
+
+        return Err(VaultError::DestinationNotAllowed.into());
+    }
+
+    Ok(())
+}
+
+/// Finds an optional account by address. The treasury and the allowlist PDA
+/// are only needed when a fee or the allowlist applies, so clients append
+/// them after the accounts of the instruction instead of at a fixed position.
+fn find_account<'a, 'b>(
+    accounts: &'a [solana_program::account_info::AccountInfo<'b>],
+    key: &solana_program::pubkey::Pubkey,
+) -> Option<&'a solana_program::account_info::AccountInfo<'b>> {
+    accounts.iter().find(|account| account.key == key)
+}
+
+/// Checks that `guardians_account` is the guardians PDA of this vault and
+/// returns its bump.
+fn check_guardians_address(
+    program_id: &solana_program::pubkey::Pubkey,
+    user_pda_account: &solana_program::account_info::AccountInfo,
+    guardians_account: &solana_program::account_info::AccountInfo,
+) -> Result<u8, solana_program::program_error::ProgramError> {
+    let (guardians_address, guardians_bump) =
+        state::Guardians::find_address(program_id, user_pda_account.key);
+
+    if guardians_account.key != &guardians_address {
+        return Err(VaultError::InvalidGuardiansAddress.into());
+    }
+
+    Ok(guardians_bump)
+}
+
+fn load_guardians(
+    program_id: &solana_program::pubkey::Pubkey,
+    vault: &state::Vault,
+    user_pda_account: &solana_program::account_info::AccountInfo,
+    guardians_account: &solana_program::account_info::AccountInfo,
+) -> Result<state::Guardians, solana_program::program_error::ProgramError> {
+    check_guardians_address(program_id, user_pda_account, guardians_account)?;
+    validation::assert_state_account(guardians_account, program_id)?;
+
+    let guardians = state::Guardians::unpack(&guardians_account.try_borrow_data()?)?;
+    vault.ensure_companion(guardians.vault_created_slot)?;
+
+    Ok(guardians)
+}
+
+/// Checks that `allowance_account` is the allowance PDA of the delegate on
+/// this vault and returns its bump.
+fn check_allowance_address(
+    program_id: &solana_program::pubkey::Pubkey,
+    user_pda_account: &solana_program::account_info::AccountInfo,
+    delegate_account: &solana_program::account_info::AccountInfo,
+    allowance_account: &solana_program::account_info::AccountInfo,
+) -> Result<u8, solana_program::program_error::ProgramError> {
+    let (allowance_address, allowance_bump) =
+        state::Allowance::find_address(program_id, user_pda_account.key, delegate_account.key);
+
+    if allowance_account.key != &allowance_address {
+        return Err(VaultError::InvalidAllowanceAddress.into());
+    }
+
+    Ok(allowance_bump)
+}
+
+fn load_existing_vault(
+    user_account: &solana_program::account_info::AccountInfo,
+    user_pda_account: &solana_program::account_info::AccountInfo,
+) -> Result<state::Vault, solana_program::program_error::ProgramError> {
+    let vault = state::Vault::unpack(&user_pda_account.try_borrow_data()?)?;
+
+    if vault.authority() != *user_account.key {
+        return Err(VaultError::InvalidVaultOwner.into());
+    }
+
+    Ok(vault)
+}
+
+/// Accepts a vault PDA that was not derived from `user_account` if guardians
+/// rotated its authority to that key, and returns the vault's bump. Older
+/// layouts are read too, so a recovered vault can still be migrated.
+fn recovered_vault_bump(
+    program_id: &solana_program::pubkey::Pubkey,
+    user_account: &solana_program::account_info::AccountInfo,
+    user_pda_account: &solana_program::account_info::AccountInfo,
+    vault_name: &[u8],
+) -> Result<u8, solana_program::program_error::ProgramError> {
+    if user_pda_account.owner != program_id {
+        return Err(VaultError::InvalidVaultAddress.into());
+    }
+
+    let vault = state::Vault::unpack_upgraded(&user_pda_account.try_borrow_data()?)
+        .map_err(|_| VaultError::InvalidVaultAddress)?;
+
+    let vault_address = solana_program::pubkey::Pubkey::create_program_address(
+        &signer_seeds(&vault.owner, vault_name, &[vault.bump]),
+        program_id,
+    )
+    .map_err(|_| VaultError::InvalidVaultAddress)?;
+
+    if vault.authority() != *user_account.key || user_pda_account.key != &vault_address {
+        return Err(VaultError::InvalidVaultAddress.into());
+    }
+
+    solana_program::msg!("Vault of {} recovered to {}", vault.owner, user_account.key);
+
+    Ok(vault.bump)
+}
+
+fn signer_seeds<'a>(
+    owner: &'a solana_program::pubkey::Pubkey,
+    vault_name: &'a [u8],
+    bump: &'a [u8],
+) -> Vec<&'a [u8]> {
+    let mut seeds = state::Vault::seeds(owner, vault_name);
+    seeds.push(bump);
+    seeds
+}
+
+/// Opens a stream funded from the sender's vault. The escrowed lamports
+/// leave the vault like a withdrawal, withdraw fee included, and sit in the
+/// stream PDA until they are claimed or refunded.
+#[allow(clippy::too_many_arguments)]
+fn process_open_stream<'a>(
+    program_id: &solana_program::pubkey::Pubkey,
+    accounts: &[solana_program::account_info::AccountInfo<'a>],
+    user_account: &solana_program::account_info::AccountInfo<'a>,
+    user_pda_account: &solana_program::account_info::AccountInfo<'a>,
+    stream_accounts: &StreamAccounts<'_, 'a>,
+    config: Option<&state::Config>,
+    deposit_amount: u64,
+    stream_id: u64,
+    rate: u64,
+    clock: &solana_program::clock::Clock,
+) -> solana_program::entrypoint::ProgramResult {
+    solana_program::msg!("Opening stream");
+
+    let mut vault = load_existing_vault(user_account, user_pda_account)?;
+    vault.ensure_single_owner()?;
+    vault.ensure_unlocked(clock)?;
+
+    validation::assert_writable(user_account)?;
+
+    check_destination(
+        program_id,
+        accounts,
+        &vault,
+        user_pda_account,
+        stream_accounts.recipient_account.key,
+        clock,
+    )?;
+
+    solana_program::msg!(
+        "Stream {} to {}: {} lamports at {} per second",
+        stream_id,
+        stream_accounts.recipient_account.key,
+        deposit_amount,
+        rate,
+    );
+
+    if deposit_amount == 0 || rate == 0 {
+        return Err(VaultError::ZeroAmount.into());
+    }
+
+    let (stream_address, stream_bump) = state::Stream::find_address(
+        program_id,
+        user_account.key,
+        stream_accounts.recipient_account.key,
+        stream_id,
+    );
+
+    if stream_accounts.stream_account.key != &stream_address {
+        return Err(VaultError::InvalidStreamAddress.into());
+    }
+
+    if !stream_accounts.stream_account.data_is_empty() {
+        return Err(VaultError::StreamAlreadyExists.into());
+    }
+
+    let rent = solana_program::rent::Rent::get()?;
+
+    solana_program::program::invoke_signed(
+        &solana_program::system_instruction::create_account(
+            user_account.key,
+            stream_accounts.stream_account.key,
+            rent.minimum_balance(state::Stream::LEN),
+            state::Stream::LEN as u64,
+            program_id,
+        ),
+        &[user_account.clone(), stream_accounts.stream_account.clone()],
+        &[&[
+            state::STREAM_SEED,
+            user_account.key.as_ref(),
+            stream_accounts.recipient_account.key.as_ref(),
+            &stream_id.to_le_bytes(),
+            &[stream_bump],
+        ]],
+    )?;
+
+    let fee = protocol_fee(accounts, config, &vault, |config| {
+        config.withdraw_fee(deposit_amount)
+    })?;
+
+    withdraw_lamports(
+        &mut vault,
+        user_pda_account,
+        stream_accounts.stream_account,
+        deposit_amount,
+        fee,
+        clock,
+    )?;
+
+    state::Stream::new(
+        *user_account.key,
+        *stream_accounts.recipient_account.key,
+        *user_pda_account.key,
+        stream_id,
+        stream_bump,
+        rate,
+        deposit_amount - fee.map_or(0, |(_, fee_amount)| fee_amount),
+        clock.unix_timestamp,
+    )
+    .pack(&mut stream_accounts.stream_account.try_borrow_mut_data()?)
+}
+
+/// Accounts shared by the stream instructions, following the config PDA.
+struct StreamAccounts<'b, 'a> {
+    recipient_account: &'b solana_program::account_info::AccountInfo<'a>,
+    stream_account: &'b solana_program::account_info::AccountInfo<'a>,
+}
+
+impl<'b, 'a> StreamAccounts<'b, 'a> {
+    fn parse(
+        accounts_iter: &mut std::slice::Iter<'b, solana_program::account_info::AccountInfo<'a>>,
+    ) -> Result<Self, solana_program::program_error::ProgramError> {
+        let stream_accounts = Self {
+            recipient_account: solana_program::account_info::next_account_info(accounts_iter)?,
+            stream_account: solana_program::account_info::next_account_info(accounts_iter)?,
+        };
+
+        validation::assert_writable(stream_accounts.stream_account)?;
+
+        Ok(stream_accounts)
+    }
+
+    /// Loads an open stream and checks it was funded from `user_pda_account`.
+    fn load(
+        &self,
+        program_id: &solana_program::pubkey::Pubkey,
+        user_account: &solana_program::account_info::AccountInfo,
+        user_pda_account: &solana_program::account_info::AccountInfo,
+        stream_id: u64,
+    ) -> Result<state::Stream, solana_program::program_error::ProgramError> {
+        let (stream_address, _) = state::Stream::find_address(
+            program_id,
+            user_account.key,
+            self.recipient_account.key,
+            stream_id,
+        );
+
+        if self.stream_account.key != &stream_address {
+            return Err(VaultError::InvalidStreamAddress.into());
+        }
+
+        validation::assert_owner(self.stream_account, program_id)?;
+
+        let stream = state::Stream::unpack(&self.stream_account.try_borrow_data()?)?;
+
+        if stream.vault != *user_pda_account.key {
+            return Err(VaultError::InvalidVaultAddress.into());
+        }
+
+        Ok(stream)
+    }
+}
+
+/// Accounts shared by the multisig proposal instructions, following the
+/// config PDA. The owner account is the signing multisig owner.
+struct MultisigAccounts<'b, 'a> {
+    multisig_account: &'b solana_program::account_info::AccountInfo<'a>,
+    proposal_account: &'b solana_program::account_info::AccountInfo<'a>,
+    owner_account: &'b solana_program::account_info::AccountInfo<'a>,
+}
+
+impl<'b, 'a> MultisigAccounts<'b, 'a> {
+    fn parse(
+        accounts_iter: &mut std::slice::Iter<'b, solana_program::account_info::AccountInfo<'a>>,
+    ) -> Result<Self, solana_program::program_error::ProgramError> {
+        let multisig_accounts = Self {
+            multisig_account: solana_program::account_info::next_account_info(accounts_iter)?,
+            proposal_account: solana_program::account_info::next_account_info(accounts_iter)?,
+            owner_account: solana_program::account_info::next_account_info(accounts_iter)?,
+        };
+
+        validation::assert_signer(multisig_accounts.owner_account)?;
+        validation::assert_writable(multisig_accounts.proposal_account)?;
+
+        Ok(multisig_accounts)
+    }
+
+    fn load_multisig(
+        &self,
+        program_id: &solana_program::pubkey::Pubkey,
+        user_pda_account: &solana_program::account_info::AccountInfo,
+    ) -> Result<state::Multisig, solana_program::program_error::ProgramError> {
+        let (multisig_address, _) = state::Multisig::find_address(program_id, user_pda_account.key);
+
+        if self.multisig_account.key != &multisig_address {
+            return Err(VaultError::InvalidMultisigAddress.into());
+        }
+
+        validation::assert_owner(self.multisig_account, program_id)?;
+
+        state::Multisig::unpack(&self.multisig_account.try_borrow_data()?)
+    }
+
+    fn load_proposal(
+        &self,
+        program_id: &solana_program::pubkey::Pubkey,
+        user_pda_account: &solana_program::account_info::AccountInfo,
+        proposal_id: u64,
+    ) -> Result<state::Proposal, solana_program::program_error::ProgramError> {
+        let (proposal_address, _) =
+            state::Proposal::find_address(program_id, user_pda_account.key, proposal_id);
+
+        if self.proposal_account.key != &proposal_address {
+            return Err(VaultError::InvalidProposalAddress.into());
+        }
+
+        validation::assert_owner(self.proposal_account, program_id)?;
+
+        state::Proposal::unpack(&self.proposal_account.try_borrow_data()?)
+    }
+}
+
+/// Accounts shared by the token instructions, following the config PDA.
+struct TokenAccounts<'b, 'a> {
+    system_program: &'b solana_program::account_info::AccountInfo<'a>,
+    mint_account: &'b solana_program::account_info::AccountInfo<'a>,
+    user_token_account: &'b solana_program::account_info::AccountInfo<'a>,
+    vault_token_account: &'b solana_program::account_info::AccountInfo<'a>,
+    token_balance_account: &'b solana_program::account_info::AccountInfo<'a>,
+    token_program: &'b solana_program::account_info::AccountInfo<'a>,
+    associated_token_program: &'b solana_program::account_info::AccountInfo<'a>,
+    /// Extra accounts required by the mint's transfer hook, if any.
+    remaining_accounts: &'b [solana_program::account_info::AccountInfo<'a>],
+}
+
+impl<'b, 'a> TokenAccounts<'b, 'a> {
+    fn parse(
+        accounts_iter: &mut std::slice::Iter<'b, solana_program::account_info::AccountInfo<'a>>,
+        system_program: &'b solana_program::account_info::AccountInfo<'a>,
+        user_pda_account: &solana_program::account_info::AccountInfo<'a>,
+    ) -> Result<Self, solana_program::program_error::ProgramError> {
+        let token_accounts = Self {
+            system_program,
+            mint_account: solana_program::account_info::next_account_info(accounts_iter)?,
+            user_token_account: solana_program::account_info::next_account_info(accounts_iter)?,
+            vault_token_account: solana_program::account_info::next_account_info(accounts_iter)?,
+            token_balance_account: solana_program::account_info::next_account_info(accounts_iter)?,
+            token_program: solana_program::account_info::next_account_info(accounts_iter)?,
+            associated_token_program: solana_program::account_info::next_account_info(
+                accounts_iter,
+            )?,
+            remaining_accounts: accounts_iter.as_slice(),
+        };
+
+        let token_program_id = token_accounts.token_program.key;
+
+        if token_program_id != &spl_token::id() {
+            validation::assert_program(token_accounts.token_program, &spl_token_2022::id())?;
+        }
+
+        validation::assert_program(
+            token_accounts.associated_token_program,
+            &spl_associated_token_account::id(),
+        )?;
+        validation::assert_owner(token_accounts.mint_account, token_program_id)?;
+        validation::assert_writable(token_accounts.user_token_account)?;
+        validation::assert_writable(token_accounts.vault_token_account)?;
+        validation::assert_writable(token_accounts.token_balance_account)?;
+
+        let vault_token_address =
+            spl_associated_token_account::get_associated_token_address_with_program_id(
+                user_pda_account.key,
+                token_accounts.mint_account.key,
+                token_accounts.token_program.key,
+            );
+
+        if token_accounts.vault_token_account.key != &vault_token_address {
+            return Err(VaultError::InvalidTokenAccount.into());
+        }
+
+        Ok(token_accounts)
+    }
+
+    /// Returns the mint decimals, refusing mints whose extensions let a third
+    /// party move or lock tokens held by the vault.
+    fn checked_mint_decimals(&self) -> Result<u8, solana_program::program_error::ProgramError> {
+        use spl_token_2022::extension::BaseStateWithExtensions;
+
+        let mint_data = self.mint_account.try_borrow_data()?;
+        let mint =
+            spl_token_2022::extension::StateWithExtensions::<spl_token_2022::state::Mint>::unpack(
+                &mint_data,
+            )?;
+
+        for extension_type in mint.get_extension_types()? {
+            if UNSUPPORTED_MINT_EXTENSIONS.contains(&extension_type) {
+                solana_program::msg!("Unsupported mint extension: {:?}", extension_type);
+
+                return Err(VaultError::UnsupportedMintExtension.into());
+            }
+        }
+
+        Ok(mint.base.decimals)
+    }
+
+    /// Wallet that owns the token account receiving a withdrawal.
+    fn user_token_owner(
+        &self,
+    ) -> Result<solana_program::pubkey::Pubkey, solana_program::program_error::ProgramError> {
+        let account_data = self.user_token_account.try_borrow_data()?;
+        let account = spl_token_2022::extension::StateWithExtensions::<
+            spl_token_2022::state::Account,
+        >::unpack(&account_data)?;
+
+        Ok(account.base.owner)
+    }
+
+    fn vault_token_amount(&self) -> Result<u64, solana_program::program_error::ProgramError> {
+        let account_data = self.vault_token_account.try_borrow_data()?;
+        let account = spl_token_2022::extension::StateWithExtensions::<
+            spl_token_2022::state::Account,
+        >::unpack(&account_data)?;
+
+        Ok(account.base.amount)
+    }
+}
+
+/// Mint extensions that make custody unsafe: a permanent delegate can drain
+/// the vault token account, and non-transferable tokens can never leave it.
+const UNSUPPORTED_MINT_EXTENSIONS: [spl_token_2022::extension::ExtensionType; 2] = [
+    spl_token_2022::extension::ExtensionType::PermanentDelegate,
+    spl_token_2022::extension::ExtensionType::NonTransferable,
+];
+
+/// Loads the vault's balance record for a mint, creating it on first deposit.
+fn load_token_balance<'a>(
+    program_id: &solana_program::pubkey::Pubkey,
+    user_account: &solana_program::account_info::AccountInfo<'a>,
+    user_pda_account: &solana_program::account_info::AccountInfo<'a>,
+    token_accounts: &TokenAccounts<'_, 'a>,
+    clock: &solana_program::clock::Clock,
+    create_if_empty: bool,
+) -> Result<state::TokenBalance, solana_program::program_error::ProgramError> {
+    let token_balance_account = token_accounts.token_balance_account;
+    let mint_account = token_accounts.mint_account;
+
+    let (token_balance_address, bump_seed) =
+        state::TokenBalance::find_address(program_id, user_pda_account.key, mint_account.key);
+
+    if token_balance_account.key != &token_balance_address {
+        return Err(VaultError::InvalidTokenBalanceAddress.into());
+    }
+
+    validation::assert_owned_or_uninitialized(token_balance_account, program_id)?;
+
+    if create_if_empty && token_balance_account.data_is_empty() {
+        solana_program::msg!("Creating token balance account");
+
+        let rent = solana_program::rent::Rent::get()?;
+        let rent_required_lamports = rent.minimum_balance(state::TokenBalance::LEN);
+
+        if **user_account.try_borrow_lamports()? <= rent_required_lamports {
+            return Err(VaultError::InsufficientFundsForRent.into());
+        }
+
+        solana_program::program::invoke_signed(
+            &solana_program::system_instruction::create_account(
+                user_account.key,
+                token_balance_account.key,
+                rent_required_lamports,
+                state::TokenBalance::LEN as u64,
+                program_id,
+            ),
+            &[user_account.clone(), token_balance_account.clone()],
+            &[&[
+                state::TOKEN_BALANCE_SEED,
+                user_pda_account.key.as_ref(),
+                mint_account.key.as_ref(),
+                &[bump_seed],
+            ]],
+        )?;
+
+        state::TokenBalance::new(
+            *user_pda_account.key,
+            *mint_account.key,
+            bump_seed,
+            clock.unix_timestamp,
+        )
+        .pack(&mut token_balance_account.try_borrow_mut_data()?)?;
+    }
+
+    state::TokenBalance::unpack(&token_balance_account.try_borrow_data()?)
+}
+
+/// Handles the config instructions. Their accounts are
+/// `[admin, config, system program, ..]`, and `InitializeConfig` is signed by
+/// the upgrade authority in place of the admin.
+fn process_config(
+    program_id: &solana_program::pubkey::Pubkey,
+    instruction: &VaultInstruction,
+    accounts: &[solana_program::account_info::AccountInfo],
+) -> solana_program::entrypoint::ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+
+    let signer_account = solana_program::account_info::next_account_info(accounts_iter)?;
+    let config_account = solana_program::account_info::next_account_info(accounts_iter)?;
+    let _system_program = solana_program::account_info::next_account_info(accounts_iter)?;
+
+    validation::assert_signer(signer_account)?;
+
+    let (config_address, config_bump) = state::Config::find_address(program_id);
+
+    if config_account.key != &config_address {
+        return Err(VaultError::InvalidConfigAddress.into());
+    }
+
+    validation::assert_writable(config_account)?;
+
+    let clock = solana_program::clock::Clock::get()?;
+
+    if let VaultInstruction::InitializeConfig {
+        deposit_fee_bps,
+        withdraw_fee_bps,
+    } = instruction
+    {
+        solana_program::msg!("Initializing config");
+
+        let program_data_account = solana_program::account_info::next_account_info(accounts_iter)?;
+        let admin_account = solana_program::account_info::next_account_info(accounts_iter)?;
+        let treasury_account = solana_program::account_info::next_account_info(accounts_iter)?;
+
+        validation::assert_writable(signer_account)?;
+        ensure_upgrade_authority(program_id, signer_account, program_data_account)?;
+
+        if !config_account.data_is_empty() {
+            return Err(solana_program::program_error::ProgramError::AccountAlreadyInitialized);
+        }
+
+        let mut config = state::Config::new(
+            config_bump,
+            *admin_account.key,
+            *treasury_account.key,
+            clock.unix_timestamp,
+        );
+        config.set_fees(*deposit_fee_bps, *withdraw_fee_bps)?;
+
+        let rent = solana_program::rent::Rent::get()?;
+
+        solana_program::program::invoke_signed(
+            &solana_program::system_instruction::create_account(
+                signer_account.key,
+                config_account.key,
+                rent.minimum_balance(state::Config::LEN),
+                state::Config::LEN as u64,
+                program_id,
+            ),
+            &[signer_account.clone(), config_account.clone()],
+            &[&[state::CONFIG_SEED, &[config_bump]]],
+        )?;
+
+        config.pack(&mut config_account.try_borrow_mut_data()?)?;
+
+        solana_program::msg!("Config initialized. Admin: {}", config.admin);
+
+        return Ok(());
+    }
+
+    validation::assert_owner(config_account, program_id)?;
+
+    let mut config = state::Config::unpack(&config_account.try_borrow_data()?)?;
+
+    if config.admin != *signer_account.key {
+        return Err(VaultError::InvalidConfigAdmin.into());
+    }
+
+    if let VaultInstruction::SetFeeExempt { exempt } = instruction {
+        let vault_account = solana_program::account_info::next_account_info(accounts_iter)?;
+
+        validation::assert_state_account(vault_account, program_id)?;
+
+        let mut vault = state::Vault::unpack(&vault_account.try_borrow_data()?)?;
+
+        vault.fee_exempt = *exempt;
+        vault.pack(&mut vault_account.try_borrow_mut_data()?)?;
+
+        solana_program::msg!(
+            "Vault {} fee exempt: {}",
+            vault_account.key,
+            vault.fee_exempt
+        );
+
+        return Ok(());
+    }
+
+    if let VaultInstruction::SetPaused { paused } = instruction {
+        config.paused = *paused;
+
+        solana_program::msg!("Program paused: {}", config.paused);
+    } else if let VaultInstruction::UpdateConfig {
+        new_admin,
+        treasury,
+        deposit_fee_bps,
+        withdraw_fee_bps,
+    } = instruction
+    {
+        config.admin = *new_admin;
+        config.treasury = *treasury;
+        config.set_fees(*deposit_fee_bps, *withdraw_fee_bps)?;
+
+        solana_program::msg!(
+            "Config updated. Admin: {}, treasury: {}, deposit fee: {} bps, withdraw fee: {} bps",
+            config.admin,
+            config.treasury,
+            config.deposit_fee_bps,
+            config.withdraw_fee_bps,
+        );
+    }
+
+    config.updated_at = clock.unix_timestamp;
+    config.pack(&mut config_account.try_borrow_mut_data()?)
+}
+
+/// Checks that `signer_account` is the upgrade authority recorded in the
+/// program's `ProgramData` account.
+fn ensure_upgrade_authority(
+    program_id: &solana_program::pubkey::Pubkey,
+    signer_account: &solana_program::account_info::AccountInfo,
+    program_data_account: &solana_program::account_info::AccountInfo,
+) -> solana_program::entrypoint::ProgramResult {
+    let (program_data_address, _) = solana_program::pubkey::Pubkey::find_program_address(
+        &[program_id.as_ref()],
+        &solana_program::bpf_loader_upgradeable::id(),
+    );
+
+    if program_data_account.key != &program_data_address {
+        return Err(solana_program::program_error::ProgramError::InvalidAccountData);
+    }
+
+    validation::assert_owner(
+        program_data_account,
+        &solana_program::bpf_loader_upgradeable::id(),
+    )?;
+
+    let program_data = solana_program::program_utils::limited_deserialize::<
+        solana_program::bpf_loader_upgradeable::UpgradeableLoaderState,
+    >(
+        &program_data_account.try_borrow_data()?,
+        solana_program::bpf_loader_upgradeable::UpgradeableLoaderState::size_of_programdata_metadata() as u64,
+    )
+    .map_err(|_| solana_program::program_error::ProgramError::InvalidAccountData)?;
+
+    match program_data {
+        solana_program::bpf_loader_upgradeable::UpgradeableLoaderState::ProgramData {
+            upgrade_authority_address: Some(upgrade_authority),
+            ..
+        } if upgrade_authority == *signer_account.key => Ok(()),
+        _ => Err(VaultError::InvalidUpgradeAuthority.into()),
+    }
+}
+
+/// Loads the program config, `None` until it is initialized.
+fn load_config(
+    program_id: &solana_program::pubkey::Pubkey,
+    config_account: &solana_program::account_info::AccountInfo,
+) -> Result<Option<state::Config>, solana_program::program_error::ProgramError> {
+    let (config_address, _) = state::Config::find_address(program_id);
+
+    if config_account.key != &config_address {
+        return Err(VaultError::InvalidConfigAddress.into());
+    }
+
+    validation::assert_owned_or_uninitialized(config_account, program_id)?;
+
+    if config_account.data_is_empty() {
+        return Ok(None);
+    }
+
+    state::Config::unpack(&config_account.try_borrow_data()?).map(Some)
+}
+
+/// Returns the treasury account and the protocol fee `fee_amount` computes
+/// from the config, or `None` when no fee applies.
+fn protocol_fee<'a, 'b>(
+    accounts: &'a [solana_program::account_info::AccountInfo<'b>],
+    config: Option<&state::Config>,
+    vault: &state::Vault,
+    fee_amount: impl Fn(&state::Config) -> u64,
+) -> Result<
+    Option<(&'a solana_program::account_info::AccountInfo<'b>, u64)>,
+    solana_program::program_error::ProgramError,
+> {
+    let Some(config) = config.filter(|_| !vault.fee_exempt) else {
+        return Ok(None);
+    };
+
+    let fee = fee_amount(config);
+
+    if fee == 0 {
+        return Ok(None);
+    }
+
+    let treasury_account =
+        find_account(accounts, &config.treasury).ok_or(VaultError::InvalidTreasuryAddress)?;
+
+    validation::assert_writable(treasury_account)?;
+
+    Ok(Some((treasury_account, fee)))
+}
+
+/// Upgrades a vault to the current layout. Legacy 8-byte vaults are rebuilt
+/// around their stored balance, older versioned vaults are zero-extended.
+fn process_migrate<'a>(
+    program_id: &solana_program::pubkey::Pubkey,
+    user_account: &solana_program::account_info::AccountInfo<'a>,
+    user_pda_account: &solana_program::account_info::AccountInfo<'a>,
+    bump_seed: u8,
+    clock: &solana_program::clock::Clock,
+) -> solana_program::entrypoint::ProgramResult {
+    solana_program::msg!("Processing migration");
+
+    validation::assert_writable(user_account)?;
+    validation::assert_state_account(user_pda_account, program_id)?;
+
+    let layout_version = state::Vault::layout_version(&user_pda_account.try_borrow_data()?);
+
+    let vault = match layout_version {
+        Some(state::VAULT_VERSION) => return Err(VaultError::AlreadyMigrated.into()),
+
+        Some(version) if version < state::VAULT_VERSION => {
+            solana_program::msg!("Vault layout version: {}", version);
+
+            user_pda_account.realloc(state::Vault::LEN, true)?;
+            user_pda_account.try_borrow_mut_data()?[8] = state::VAULT_VERSION;
+
+            let mut vault = state::Vault::unpack(&user_pda_account.try_borrow_data()?)?;
+
+            if vault.authority() != *user_account.key {
+                return Err(VaultError::InvalidVaultOwner.into());
+            }
+
+            vault.updated_at = clock.unix_timestamp;
+            vault
+        }
+
+        Some(_) => return Err(VaultError::UnsupportedVaultVersion.into()),
+
+        None if user_pda_account.data_len() == state::LEGACY_VAULT_LEN => {
+            let legacy_balance = u64_from_data(&user_pda_account.try_borrow_data()?)?;
+
+            solana_program::msg!("Legacy balance: {} lamports", legacy_balance);
+
+            user_pda_account.realloc(state::Vault::LEN, true)?;
+
+            state::Vault::from_legacy(
+                *user_account.key,
+                bump_seed,
+                legacy_balance,
+                clock.unix_timestamp,
+            )
+        }
+
+        None => return Err(VaultError::InvalidAccountDiscriminator.into()),
+    };
+
+    let rent = solana_program::rent::Rent::get()?;
+    let required_lamports = rent
+        .minimum_balance(state::Vault::LEN)
+        .checked_add(vault.balance)
+        .ok_or(VaultError::BalanceOverflow)?;
+    let top_up_lamports = required_lamports.saturating_sub(user_pda_account.lamports());
+
+    if top_up_lamports > 0 {
+        solana_program::msg!("Rent top up: {} lamports", top_up_lamports);
+
+        if user_account.lamports() < top_up_lamports {
+            return Err(VaultError::InsufficientFundsForRent.into());
+        }
+
+        solana_program::program::invoke(
+            &solana_program::system_instruction::transfer(
+                user_account.key,
+                user_pda_account.key,
+                top_up_lamports,
+            ),
+            &[user_account.clone(), user_pda_account.clone()],
+        )?;
+    }
+
+    vault.pack(&mut user_pda_account.try_borrow_mut_data()?)?;
+
+    // Legacy vaults predate the events, so their balance enters the event
+    // stream here.
+    if layout_version.is_none() {
+        VaultEvent::BalanceAdjusted(event::BalanceAdjusted {
+            vault: *user_pda_account.key,
+            mint: solana_program::pubkey::Pubkey::default(),
+            previous_balance: 0,
+            balance: vault.balance,
+            timestamp: clock.unix_timestamp,
+        })
+        .emit();
+    }
+
+    solana_program::msg!("Migration successful. PDA balance: {}", vault.balance);
+
+    Ok(())
+}
+
+fn u64_from_data(data: &[u8]) -> Result<u64, solana_program::program_error::ProgramError> {
+    if data.len() < 8 {
+        return Err(solana_program::program_error::ProgramError::AccountDataTooSmall);
+    }
+
+    let parsed = data[..8]
+        .try_into()
+        .map_err(|_| solana_program::program_error::ProgramError::InvalidAccountData)?;
+
+    Ok(u64::from_le_bytes(parsed))
+}
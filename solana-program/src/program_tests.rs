@@ -0,0 +1,3800 @@
+use {
+    crate::{
+        error::VaultError,
+        instruction::{LockKind, VaultInstruction, VestingTerms},
+        state,
+    },
+    solana_program_test::{processor, BanksClientError, ProgramTest, ProgramTestContext},
+    solana_sdk::{
+        account::Account,
+        clock::Clock,
+        instruction::{AccountMeta, Instruction, InstructionError},
+        pubkey::Pubkey,
+        rent::Rent,
+        signature::{Keypair, Signer},
+        transaction::{Transaction, TransactionError},
+    },
+    spl_token_2022::extension::{ExtensionType, StateWithExtensions},
+};
+
+fn program_test(program_id: Pubkey) -> ProgramTest {
+    ProgramTest::new(
+        "solana_program",
+        program_id,
+        processor!(crate::process_instruction),
+    )
+}
+
+fn vault_address(program_id: &Pubkey, user: &Pubkey) -> Pubkey {
+    state::Vault::find_address(program_id, user, &[]).0
+}
+
+fn vault_instruction(
+    program_id: Pubkey,
+    user: &Pubkey,
+    instruction: VaultInstruction,
+) -> Instruction {
+    let vault = state::Vault::find_address(&program_id, user, instruction.vault_name()).0;
+
+    Instruction::new_with_borsh(
+        program_id,
+        &instruction,
+        vec![
+            AccountMeta::new(*user, true),
+            AccountMeta::new(vault, false),
+            AccountMeta::new_readonly(solana_program::system_program::ID, false),
+            AccountMeta::new_readonly(state::Config::find_address(&program_id).0, false),
+        ],
+    )
+}
+
+async fn send(
+    context: &mut ProgramTestContext,
+    instructions: &[Instruction],
+    signers: &[&Keypair],
+) -> Result<(), BanksClientError> {
+    let blockhash = context.get_new_latest_blockhash().await.unwrap();
+
+    let mut all_signers = vec![&context.payer];
+    all_signers.extend_from_slice(signers);
+
+    let transaction = Transaction::new_signed_with_payer(
+        instructions,
+        Some(&context.payer.pubkey()),
+        &all_signers,
+        blockhash,
+    );
+
+    context.banks_client.process_transaction(transaction).await
+}
+
+fn custom_error(result: Result<(), BanksClientError>) -> Option<VaultError> {
+    match result.unwrap_err().unwrap() {
+        TransactionError::InstructionError(_, InstructionError::Custom(code)) => {
+            VaultError::from_code(code)
+        }
+        _ => None,
+    }
+}
+
+async fn vault_account(context: &mut ProgramTestContext, address: Pubkey) -> Account {
+    context
+        .banks_client
+        .get_account(address)
+        .await
+        .unwrap()
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_migrate_legacy_vault() {
+    let program_id = Pubkey::new_unique();
+    let user = Keypair::new();
+    let vault = vault_address(&program_id, &user.pubkey());
+    let legacy_balance = 100_000;
+    let legacy_lamports = Rent::default().minimum_balance(state::LEGACY_VAULT_LEN) + legacy_balance;
+
+    let mut program_test = program_test(program_id);
+    program_test.add_account(
+        user.pubkey(),
+        Account::new(1_000_000_000, 0, &solana_program::system_program::ID),
+    );
+    program_test.add_account(
+        vault,
+        Account {
+            lamports: legacy_lamports,
+            data: legacy_balance.to_le_bytes().to_vec(),
+            owner: program_id,
+            executable: false,
+            rent_epoch: 0,
+        },
+    );
+
+    let mut context = program_test.start_with_context().await;
+
+    let migrate = vault_instruction(
+        program_id,
+        &user.pubkey(),
+        VaultInstruction::Migrate { vault_name: vec![] },
+    );
+    send(&mut context, &[migrate], &[&user]).await.unwrap();
+
+    let account = vault_account(&mut context, vault).await;
+    let migrated = state::Vault::unpack(&account.data).unwrap();
+
+    assert_eq!(account.data.len(), state::Vault::LEN);
+    assert_eq!(
+        account.lamports,
+        Rent::default().minimum_balance(state::Vault::LEN) + legacy_balance,
+    );
+    assert_eq!(migrated.version, state::VAULT_VERSION);
+    assert_eq!(migrated.owner, user.pubkey());
+    assert_eq!(migrated.balance, legacy_balance);
+    assert_eq!(migrated.total_deposited, legacy_balance);
+
+    let withdraw = vault_instruction(
+        program_id,
+        &user.pubkey(),
+        VaultInstruction::Withdraw {
+            vault_name: vec![],
+            amount: legacy_balance,
+        },
+    );
+    send(&mut context, &[withdraw], &[&user]).await.unwrap();
+
+    let account = vault_account(&mut context, vault).await;
+
+    assert_eq!(state::Vault::unpack(&account.data).unwrap().balance, 0);
+}
+
+#[tokio::test]
+async fn test_migrate_twice_fails() {
+    let program_id = Pubkey::new_unique();
+    let user = Keypair::new();
+    let vault = vault_address(&program_id, &user.pubkey());
+
+    let mut program_test = program_test(program_id);
+    program_test.add_account(
+        user.pubkey(),
+        Account::new(1_000_000_000, 0, &solana_program::system_program::ID),
+    );
+    program_test.add_account(
+        vault,
+        Account {
+            lamports: Rent::default().minimum_balance(state::LEGACY_VAULT_LEN),
+            data: 0u64.to_le_bytes().to_vec(),
+            owner: program_id,
+            executable: false,
+            rent_epoch: 0,
+        },
+    );
+
+    let mut context = program_test.start_with_context().await;
+
+    let migrate = vault_instruction(
+        program_id,
+        &user.pubkey(),
+        VaultInstruction::Migrate { vault_name: vec![] },
+    );
+    send(&mut context, &[migrate], &[&user]).await.unwrap();
+
+    let migrate = vault_instruction(
+        program_id,
+        &user.pubkey(),
+        VaultInstruction::Migrate { vault_name: vec![] },
+    );
+    let result = send(&mut context, &[migrate], &[&user]).await;
+
+    assert_eq!(custom_error(result), Some(VaultError::AlreadyMigrated));
+}
+
+#[tokio::test]
+async fn test_migrate_previous_layout_version() {
+    let program_id = Pubkey::new_unique();
+    let user = Keypair::new();
+    let (vault, bump) = state::Vault::find_address(&program_id, &user.pubkey(), &[]);
+
+    // Version 1 layout: the first 82 bytes, everything before `name_len`.
+    let mut data = vec![0u8; state::Vault::LEN];
+    state::Vault {
+        balance: 7_000,
+        total_deposited: 9_000,
+        total_withdrawn: 2_000,
+        ..state::Vault::new(user.pubkey(), bump, &[], 1)
+    }
+    .pack(&mut data)
+    .unwrap();
+    data.truncate(82);
+    data[8] = 1;
+
+    let mut program_test = program_test(program_id);
+    program_test.add_account(
+        user.pubkey(),
+        Account::new(1_000_000_000, 0, &solana_program::system_program::ID),
+    );
+    program_test.add_account(
+        vault,
+        Account {
+            lamports: Rent::default().minimum_balance(data.len()) + 7_000,
+            data,
+            owner: program_id,
+            executable: false,
+            rent_epoch: 0,
+        },
+    );
+
+    let mut context = program_test.start_with_context().await;
+
+    let migrate = vault_instruction(
+        program_id,
+        &user.pubkey(),
+        VaultInstruction::Migrate { vault_name: vec![] },
+    );
+    send(&mut context, &[migrate], &[&user]).await.unwrap();
+
+    let account = vault_account(&mut context, vault).await;
+    let migrated = state::Vault::unpack(&account.data).unwrap();
+
+    assert_eq!(
+        account.lamports,
+        Rent::default().minimum_balance(state::Vault::LEN) + 7_000,
+    );
+    assert_eq!(migrated.version, state::VAULT_VERSION);
+    assert_eq!(migrated.balance, 7_000);
+    assert_eq!(migrated.total_deposited, 9_000);
+    assert_eq!(migrated.total_withdrawn, 2_000);
+    assert_eq!(migrated.name(), b"");
+}
+
+#[tokio::test]
+async fn test_migrate_recovered_vault() {
+    let program_id = Pubkey::new_unique();
+    let user = Keypair::new();
+    let new_authority = Keypair::new();
+    let (vault, bump) = state::Vault::find_address(&program_id, &user.pubkey(), &[]);
+
+    // Version 8 layout, recovered to `new_authority`: everything before
+    // `fee_exempt`.
+    let mut data = vec![0u8; state::Vault::LEN];
+    state::Vault {
+        balance: 7_000,
+        authority: new_authority.pubkey(),
+        ..state::Vault::new(user.pubkey(), bump, &[], 1)
+    }
+    .pack(&mut data)
+    .unwrap();
+    data.truncate(state::Vault::LEN - 11);
+    data[8] = 8;
+
+    let mut program_test = program_test(program_id);
+    program_test.add_account(
+        new_authority.pubkey(),
+        Account::new(1_000_000_000, 0, &solana_program::system_program::ID),
+    );
+    program_test.add_account(
+        vault,
+        Account {
+            lamports: Rent::default().minimum_balance(data.len()) + 7_000,
+            data,
+            owner: program_id,
+            executable: false,
+            rent_epoch: 0,
+        },
+    );
+
+    let mut context = program_test.start_with_context().await;
+
+    let migrate = |signer: &Pubkey| {
+        let mut migrate = vault_instruction(
+            program_id,
+            signer,
+            VaultInstruction::Migrate { vault_name: vec![] },
+        );
+        migrate.accounts[1].pubkey = vault;
+        migrate
+    };
+
+    let result = send(&mut context, &[migrate(&user.pubkey())], &[&user]).await;
+    assert_eq!(custom_error(result), Some(VaultError::InvalidVaultOwner));
+
+    send(
+        &mut context,
+        &[migrate(&new_authority.pubkey())],
+        &[&new_authority],
+    )
+    .await
+    .unwrap();
+
+    let migrated = state::Vault::unpack(&vault_account(&mut context, vault).await.data).unwrap();
+
+    assert_eq!(migrated.version, state::VAULT_VERSION);
+    assert_eq!(migrated.owner, user.pubkey());
+    assert_eq!(migrated.authority(), new_authority.pubkey());
+    assert_eq!(migrated.balance, 7_000);
+}
+
+#[tokio::test]
+async fn test_legacy_vault_requires_migration() {
+    let program_id = Pubkey::new_unique();
+    let user = Keypair::new();
+    let vault = vault_address(&program_id, &user.pubkey());
+
+    let mut program_test = program_test(program_id);
+    program_test.add_account(
+        user.pubkey(),
+        Account::new(1_000_000_000, 0, &solana_program::system_program::ID),
+    );
+    program_test.add_account(
+        vault,
+        Account {
+            lamports: Rent::default().minimum_balance(state::LEGACY_VAULT_LEN) + 5_000,
+            data: 5_000u64.to_le_bytes().to_vec(),
+            owner: program_id,
+            executable: false,
+            rent_epoch: 0,
+        },
+    );
+
+    let mut context = program_test.start_with_context().await;
+
+    let deposit = vault_instruction(
+        program_id,
+        &user.pubkey(),
+        VaultInstruction::Deposit {
+            vault_name: vec![],
+            amount: 1_000,
+        },
+    );
+    let result = send(&mut context, &[deposit], &[&user]).await;
+
+    assert_eq!(
+        custom_error(result),
+        Some(VaultError::InvalidAccountDiscriminator)
+    );
+}
+
+fn token_instruction(
+    program_id: Pubkey,
+    user: &Pubkey,
+    mint: &Pubkey,
+    token_program: &Pubkey,
+    instruction: VaultInstruction,
+) -> Instruction {
+    let vault = vault_address(&program_id, user);
+
+    Instruction::new_with_borsh(
+        program_id,
+        &instruction,
+        vec![
+            AccountMeta::new(*user, true),
+            AccountMeta::new(vault, false),
+            AccountMeta::new_readonly(solana_program::system_program::ID, false),
+            AccountMeta::new_readonly(state::Config::find_address(&program_id).0, false),
+            AccountMeta::new_readonly(*mint, false),
+            AccountMeta::new(token_address(user, mint, token_program), false),
+            AccountMeta::new(token_address(&vault, mint, token_program), false),
+            AccountMeta::new(
+                state::TokenBalance::find_address(&program_id, &vault, mint).0,
+                false,
+            ),
+            AccountMeta::new_readonly(*token_program, false),
+            AccountMeta::new_readonly(spl_associated_token_account::id(), false),
+        ],
+    )
+}
+
+fn token_address(owner: &Pubkey, mint: &Pubkey, token_program: &Pubkey) -> Pubkey {
+    spl_associated_token_account::get_associated_token_address_with_program_id(
+        owner,
+        mint,
+        token_program,
+    )
+}
+
+/// Creates a mint with the given extensions, initialized by `extension_instructions`,
+/// and mints `amount` tokens to the owner's associated token account.
+async fn create_mint(
+    context: &mut ProgramTestContext,
+    owner: &Pubkey,
+    amount: u64,
+    token_program: &Pubkey,
+    extension_types: &[ExtensionType],
+    extension_instructions: impl FnOnce(&Pubkey) -> Vec<Instruction>,
+) -> Pubkey {
+    let mint = Keypair::new();
+    let payer = context.payer.pubkey();
+    let rent = context.banks_client.get_rent().await.unwrap();
+    let space =
+        ExtensionType::try_calculate_account_len::<spl_token_2022::state::Mint>(extension_types)
+            .unwrap();
+
+    let mut instructions = vec![solana_sdk::system_instruction::create_account(
+        &payer,
+        &mint.pubkey(),
+        rent.minimum_balance(space),
+        space as u64,
+        token_program,
+    )];
+    instructions.extend(extension_instructions(&mint.pubkey()));
+    instructions.extend([
+        spl_token_2022::instruction::initialize_mint2(
+            token_program,
+            &mint.pubkey(),
+            &payer,
+            None,
+            6,
+        )
+        .unwrap(),
+        spl_associated_token_account::instruction::create_associated_token_account(
+            &payer,
+            owner,
+            &mint.pubkey(),
+            token_program,
+        ),
+        spl_token_2022::instruction::mint_to(
+            token_program,
+            &mint.pubkey(),
+            &token_address(owner, &mint.pubkey(), token_program),
+            &payer,
+            &[],
+            amount,
+        )
+        .unwrap(),
+    ]);
+
+    send(context, &instructions, &[&mint]).await.unwrap();
+
+    mint.pubkey()
+}
+
+async fn token_amount(context: &mut ProgramTestContext, address: Pubkey) -> u64 {
+    let account = vault_account(context, address).await;
+
+    StateWithExtensions::<spl_token_2022::state::Account>::unpack(&account.data)
+        .unwrap()
+        .base
+        .amount
+}
+
+#[tokio::test]
+async fn test_token_deposit_and_withdraw() {
+    let program_id = Pubkey::new_unique();
+    let user = Keypair::new();
+    let vault = vault_address(&program_id, &user.pubkey());
+
+    let mut program_test = program_test(program_id);
+    program_test.add_account(
+        user.pubkey(),
+        Account::new(1_000_000_000, 0, &solana_program::system_program::ID),
+    );
+
+    let mut context = program_test.start_with_context().await;
+
+    let mint = create_mint(
+        &mut context,
+        &user.pubkey(),
+        1_000,
+        &spl_token::id(),
+        &[],
+        |_| vec![],
+    )
+    .await;
+    let user_token = token_address(&user.pubkey(), &mint, &spl_token::id());
+    let vault_token = token_address(&vault, &mint, &spl_token::id());
+    let token_balance = state::TokenBalance::find_address(&program_id, &vault, &mint).0;
+
+    let deposit = token_instruction(
+        program_id,
+        &user.pubkey(),
+        &mint,
+        &spl_token::id(),
+        VaultInstruction::DepositToken {
+            vault_name: vec![],
+            amount: 400,
+        },
+    );
+    send(&mut context, &[deposit], &[&user]).await.unwrap();
+
+    assert_eq!(token_amount(&mut context, vault_token).await, 400);
+    assert_eq!(token_amount(&mut context, user_token).await, 600);
+
+    let withdraw = token_instruction(
+        program_id,
+        &user.pubkey(),
+        &mint,
+        &spl_token::id(),
+        VaultInstruction::WithdrawToken {
+            vault_name: vec![],
+            amount: 150,
+        },
+    );
+    send(&mut context, &[withdraw], &[&user]).await.unwrap();
+
+    assert_eq!(token_amount(&mut context, vault_token).await, 250);
+    assert_eq!(token_amount(&mut context, user_token).await, 750);
+
+    let account = vault_account(&mut context, token_balance).await;
+    let record = state::TokenBalance::unpack(&account.data).unwrap();
+
+    assert_eq!(record.vault, vault);
+    assert_eq!(record.mint, mint);
+    assert_eq!(record.balance, 250);
+    assert_eq!(record.total_deposited, 400);
+    assert_eq!(record.total_withdrawn, 150);
+
+    let withdraw = token_instruction(
+        program_id,
+        &user.pubkey(),
+        &mint,
+        &spl_token::id(),
+        VaultInstruction::WithdrawToken {
+            vault_name: vec![],
+            amount: 500,
+        },
+    );
+    let result = send(&mut context, &[withdraw], &[&user]).await;
+
+    assert_eq!(
+        custom_error(result),
+        Some(VaultError::InsufficientVaultBalance)
+    );
+}
+
+#[tokio::test]
+async fn test_token_2022_deposit_credits_amount_after_fee() {
+    let program_id = Pubkey::new_unique();
+    let user = Keypair::new();
+    let vault = vault_address(&program_id, &user.pubkey());
+    let token_program = spl_token_2022::id();
+
+    let mut program_test = program_test(program_id);
+    program_test.add_account(
+        user.pubkey(),
+        Account::new(1_000_000_000, 0, &solana_program::system_program::ID),
+    );
+
+    let mut context = program_test.start_with_context().await;
+
+    let mint = create_mint(
+        &mut context,
+        &user.pubkey(),
+        10_000,
+        &token_program,
+        &[ExtensionType::TransferFeeConfig],
+        |mint| {
+            vec![
+                spl_token_2022::extension::transfer_fee::instruction::initialize_transfer_fee_config(
+                    &token_program,
+                    mint,
+                    None,
+                    None,
+                    100,
+                    u64::MAX,
+                )
+                .unwrap(),
+            ]
+        },
+    )
+    .await;
+    let token_balance = state::TokenBalance::find_address(&program_id, &vault, &mint).0;
+
+    let deposit = token_instruction(
+        program_id,
+        &user.pubkey(),
+        &mint,
+        &token_program,
+        VaultInstruction::DepositToken {
+            vault_name: vec![],
+            amount: 5_000,
+        },
+    );
+    send(&mut context, &[deposit], &[&user]).await.unwrap();
+
+    let account = vault_account(&mut context, token_balance).await;
+    let record = state::TokenBalance::unpack(&account.data).unwrap();
+
+    assert_eq!(record.balance, 4_950);
+    assert_eq!(record.total_deposited, 4_950);
+
+    let withdraw = token_instruction(
+        program_id,
+        &user.pubkey(),
+        &mint,
+        &token_program,
+        VaultInstruction::WithdrawToken {
+            vault_name: vec![],
+            amount: 4_950,
+        },
+    );
+    send(&mut context, &[withdraw], &[&user]).await.unwrap();
+
+    let account = vault_account(&mut context, token_balance).await;
+
+    assert_eq!(
+        state::TokenBalance::unpack(&account.data).unwrap().balance,
+        0
+    );
+    assert_eq!(
+        token_amount(&mut context, token_address(&vault, &mint, &token_program)).await,
+        0
+    );
+}
+
+#[tokio::test]
+async fn test_token_2022_permanent_delegate_rejected() {
+    let program_id = Pubkey::new_unique();
+    let user = Keypair::new();
+    let token_program = spl_token_2022::id();
+
+    let mut program_test = program_test(program_id);
+    program_test.add_account(
+        user.pubkey(),
+        Account::new(1_000_000_000, 0, &solana_program::system_program::ID),
+    );
+
+    let mut context = program_test.start_with_context().await;
+
+    let delegate = Pubkey::new_unique();
+    let mint = create_mint(
+        &mut context,
+        &user.pubkey(),
+        1_000,
+        &token_program,
+        &[ExtensionType::PermanentDelegate],
+        |mint| {
+            vec![spl_token_2022::instruction::initialize_permanent_delegate(
+                &token_program,
+                mint,
+                &delegate,
+            )
+            .unwrap()]
+        },
+    )
+    .await;
+
+    let deposit = token_instruction(
+        program_id,
+        &user.pubkey(),
+        &mint,
+        &token_program,
+        VaultInstruction::DepositToken {
+            vault_name: vec![],
+            amount: 500,
+        },
+    );
+    let result = send(&mut context, &[deposit], &[&user]).await;
+
+    assert_eq!(
+        custom_error(result),
+        Some(VaultError::UnsupportedMintExtension)
+    );
+}
+
+#[tokio::test]
+async fn test_named_vaults_are_independent() {
+    let program_id = Pubkey::new_unique();
+    let user = Keypair::new();
+
+    let mut program_test = program_test(program_id);
+    program_test.add_account(
+        user.pubkey(),
+        Account::new(1_000_000_000, 0, &solana_program::system_program::ID),
+    );
+
+    let mut context = program_test.start_with_context().await;
+
+    let instructions = [
+        vault_instruction(
+            program_id,
+            &user.pubkey(),
+            VaultInstruction::Deposit {
+                vault_name: vec![],
+                amount: 1_000,
+            },
+        ),
+        vault_instruction(
+            program_id,
+            &user.pubkey(),
+            VaultInstruction::Deposit {
+                vault_name: b"payroll".to_vec(),
+                amount: 2_000,
+            },
+        ),
+        vault_instruction(
+            program_id,
+            &user.pubkey(),
+            VaultInstruction::Deposit {
+                vault_name: b"savings".to_vec(),
+                amount: 3_000,
+            },
+        ),
+        vault_instruction(
+            program_id,
+            &user.pubkey(),
+            VaultInstruction::Withdraw {
+                vault_name: b"savings".to_vec(),
+                amount: 500,
+            },
+        ),
+    ];
+    send(&mut context, &instructions, &[&user]).await.unwrap();
+
+    for (vault_name, balance) in [(&b""[..], 1_000), (b"payroll", 2_000), (b"savings", 2_500)] {
+        let address = state::Vault::find_address(&program_id, &user.pubkey(), vault_name).0;
+        let account = vault_account(&mut context, address).await;
+        let vault = state::Vault::unpack(&account.data).unwrap();
+
+        assert_eq!(vault.owner, user.pubkey());
+        assert_eq!(vault.name(), vault_name);
+        assert_eq!(vault.balance, balance);
+    }
+
+    let mut foreign = vault_instruction(
+        program_id,
+        &user.pubkey(),
+        VaultInstruction::Withdraw {
+            vault_name: b"payroll".to_vec(),
+            amount: 100,
+        },
+    );
+    foreign.accounts[1].pubkey =
+        state::Vault::find_address(&program_id, &user.pubkey(), b"savings").0;
+    let result = send(&mut context, &[foreign], &[&user]).await;
+
+    assert_eq!(custom_error(result), Some(VaultError::InvalidVaultAddress));
+}
+
+#[tokio::test]
+async fn test_close_vault_reclaims_rent() {
+    let program_id = Pubkey::new_unique();
+    let user = Keypair::new();
+    let destination = Pubkey::new_unique();
+    let vault = vault_address(&program_id, &user.pubkey());
+
+    let mut program_test = program_test(program_id);
+    program_test.add_account(
+        user.pubkey(),
+        Account::new(1_000_000_000, 0, &solana_program::system_program::ID),
+    );
+
+    let mut context = program_test.start_with_context().await;
+
+    let deposit = vault_instruction(
+        program_id,
+        &user.pubkey(),
+        VaultInstruction::Deposit {
+            vault_name: vec![],
+            amount: 50_000,
+        },
+    );
+    send(&mut context, &[deposit], &[&user]).await.unwrap();
+
+    let vault_lamports = vault_account(&mut context, vault).await.lamports;
+
+    let mut close = vault_instruction(
+        program_id,
+        &user.pubkey(),
+        VaultInstruction::Close { vault_name: vec![] },
+    );
+    close.accounts.push(AccountMeta::new(destination, false));
+    send(&mut context, &[close], &[&user]).await.unwrap();
+
+    assert_eq!(
+        vault_lamports,
+        Rent::default().minimum_balance(state::Vault::LEN) + 50_000,
+    );
+    assert_eq!(
+        vault_account(&mut context, destination).await.lamports,
+        vault_lamports,
+    );
+    assert!(context
+        .banks_client
+        .get_account(vault)
+        .await
+        .unwrap()
+        .is_none());
+
+    let deposit = vault_instruction(
+        program_id,
+        &user.pubkey(),
+        VaultInstruction::Deposit {
+            vault_name: vec![],
+            amount: 1_000,
+        },
+    );
+    send(&mut context, &[deposit], &[&user]).await.unwrap();
+
+    let account = vault_account(&mut context, vault).await;
+    let reopened = state::Vault::unpack(&account.data).unwrap();
+
+    assert_eq!(reopened.balance, 1_000);
+    assert_eq!(reopened.total_deposited, 1_000);
+}
+
+#[tokio::test]
+async fn test_close_voids_companion_accounts() {
+    let program_id = Pubkey::new_unique();
+    let user = Keypair::new();
+    let delegate = Keypair::new();
+    let guardian = Keypair::new();
+    let vault = vault_address(&program_id, &user.pubkey());
+    let guardians_address = state::Guardians::find_address(&program_id, &vault).0;
+
+    let mut program_test = program_test(program_id);
+    for account in [&user, &delegate] {
+        program_test.add_account(
+            account.pubkey(),
+            Account::new(1_000_000_000, 0, &solana_program::system_program::ID),
+        );
+    }
+
+    let mut context = program_test.start_with_context().await;
+
+    let deposit = vault_instruction(
+        program_id,
+        &user.pubkey(),
+        VaultInstruction::Deposit {
+            vault_name: vec![],
+            amount: 1_000_000,
+        },
+    );
+    let approve_delegate = |amount| {
+        delegate_instruction(
+            program_id,
+            &user.pubkey(),
+            &delegate.pubkey(),
+            VaultInstruction::ApproveDelegate {
+                vault_name: vec![],
+                amount,
+                expiry_slot: 0,
+            },
+        )
+    };
+    let mut set_guardians = vault_instruction(
+        program_id,
+        &user.pubkey(),
+        VaultInstruction::SetGuardians {
+            vault_name: vec![],
+            threshold: 1,
+            guardians: vec![guardian.pubkey()],
+        },
+    );
+    set_guardians
+        .accounts
+        .push(AccountMeta::new(guardians_address, false));
+    let mut close = vault_instruction(
+        program_id,
+        &user.pubkey(),
+        VaultInstruction::Close { vault_name: vec![] },
+    );
+    close
+        .accounts
+        .push(AccountMeta::new(delegate.pubkey(), false));
+
+    send(
+        &mut context,
+        &[deposit.clone(), approve_delegate(500_000), set_guardians],
+        &[&user],
+    )
+    .await
+    .unwrap();
+    send(&mut context, &[close], &[&user]).await.unwrap();
+
+    let slot = context.banks_client.get_root_slot().await.unwrap();
+    context.warp_to_slot(slot + 2).unwrap();
+
+    send(&mut context, &[deposit], &[&user]).await.unwrap();
+
+    let result = send(
+        &mut context,
+        &[delegate_withdraw_instruction(
+            program_id,
+            &user.pubkey(),
+            &delegate.pubkey(),
+            100_000,
+        )],
+        &[&delegate],
+    )
+    .await;
+    assert_eq!(
+        custom_error(result),
+        Some(VaultError::StaleCompanionAccount)
+    );
+
+    let mut approve_recovery = vault_instruction(
+        program_id,
+        &user.pubkey(),
+        VaultInstruction::ApproveRecovery { vault_name: vec![] },
+    );
+    approve_recovery.accounts[0].is_signer = false;
+    approve_recovery.accounts.extend([
+        AccountMeta::new(guardians_address, false),
+        AccountMeta::new_readonly(guardian.pubkey(), true),
+        AccountMeta::new_readonly(guardian.pubkey(), false),
+    ]);
+
+    let result = send(&mut context, &[approve_recovery], &[&guardian]).await;
+    assert_eq!(
+        custom_error(result),
+        Some(VaultError::StaleCompanionAccount)
+    );
+
+    // Granting again starts a fresh allowance instead of topping up the old one.
+    send(&mut context, &[approve_delegate(200_000)], &[&user])
+        .await
+        .unwrap();
+    send(
+        &mut context,
+        &[delegate_withdraw_instruction(
+            program_id,
+            &user.pubkey(),
+            &delegate.pubkey(),
+            100_000,
+        )],
+        &[&delegate],
+    )
+    .await
+    .unwrap();
+
+    let allowance = state::Allowance::unpack(
+        &vault_account(
+            &mut context,
+            state::Allowance::find_address(&program_id, &vault, &delegate.pubkey()).0,
+        )
+        .await
+        .data,
+    )
+    .unwrap();
+
+    assert_eq!(allowance.amount, 100_000);
+    assert_eq!(
+        allowance.vault_created_slot,
+        state::Vault::unpack(&vault_account(&mut context, vault).await.data)
+            .unwrap()
+            .created_slot,
+    );
+}
+
+#[tokio::test]
+async fn test_close_requires_owner_signature() {
+    let program_id = Pubkey::new_unique();
+    let user = Keypair::new();
+    let attacker = Keypair::new();
+
+    let mut program_test = program_test(program_id);
+    program_test.add_account(
+        user.pubkey(),
+        Account::new(1_000_000_000, 0, &solana_program::system_program::ID),
+    );
+
+    let mut context = program_test.start_with_context().await;
+
+    let deposit = vault_instruction(
+        program_id,
+        &user.pubkey(),
+        VaultInstruction::Deposit {
+            vault_name: vec![],
+            amount: 50_000,
+        },
+    );
+    send(&mut context, &[deposit], &[&user]).await.unwrap();
+
+    let mut close = vault_instruction(
+        program_id,
+        &attacker.pubkey(),
+        VaultInstruction::Close { vault_name: vec![] },
+    );
+    close.accounts[1].pubkey = vault_address(&program_id, &user.pubkey());
+    close
+        .accounts
+        .push(AccountMeta::new(attacker.pubkey(), false));
+    let result = send(&mut context, &[close], &[&attacker]).await;
+
+    assert_eq!(custom_error(result), Some(VaultError::InvalidVaultAddress));
+}
+
+#[tokio::test]
+async fn test_sync_books_direct_transfers() {
+    let program_id = Pubkey::new_unique();
+    let user = Keypair::new();
+    let vault = vault_address(&program_id, &user.pubkey());
+
+    let mut program_test = program_test(program_id);
+    program_test.add_account(
+        user.pubkey(),
+        Account::new(1_000_000_000, 0, &solana_program::system_program::ID),
+    );
+
+    let mut context = program_test.start_with_context().await;
+
+    let instructions = [
+        vault_instruction(
+            program_id,
+            &user.pubkey(),
+            VaultInstruction::Deposit {
+                vault_name: vec![],
+                amount: 10_000,
+            },
+        ),
+        solana_sdk::system_instruction::transfer(&user.pubkey(), &vault, 4_000),
+        vault_instruction(
+            program_id,
+            &user.pubkey(),
+            VaultInstruction::Sync { vault_name: vec![] },
+        ),
+    ];
+    send(&mut context, &instructions, &[&user]).await.unwrap();
+
+    let account = vault_account(&mut context, vault).await;
+    let synced = state::Vault::unpack(&account.data).unwrap();
+
+    assert_eq!(synced.balance, 14_000);
+    assert_eq!(synced.total_deposited, 14_000);
+
+    let withdraw = vault_instruction(
+        program_id,
+        &user.pubkey(),
+        VaultInstruction::Withdraw {
+            vault_name: vec![],
+            amount: 14_000,
+        },
+    );
+    send(&mut context, &[withdraw], &[&user]).await.unwrap();
+
+    assert_eq!(
+        vault_account(&mut context, vault).await.lamports,
+        Rent::default().minimum_balance(state::Vault::LEN),
+    );
+}
+
+#[tokio::test]
+async fn test_withdraw_keeps_rent_exemption() {
+    let program_id = Pubkey::new_unique();
+    let user = Keypair::new();
+    let (vault, bump) = state::Vault::find_address(&program_id, &user.pubkey(), &[]);
+
+    // Stored balance claims more than the account actually holds above rent.
+    let mut data = vec![0u8; state::Vault::LEN];
+    state::Vault {
+        balance: 10_000,
+        total_deposited: 10_000,
+        ..state::Vault::new(user.pubkey(), bump, &[], 0)
+    }
+    .pack(&mut data)
+    .unwrap();
+
+    let mut program_test = program_test(program_id);
+    program_test.add_account(
+        user.pubkey(),
+        Account::new(1_000_000_000, 0, &solana_program::system_program::ID),
+    );
+    program_test.add_account(
+        vault,
+        Account {
+            lamports: Rent::default().minimum_balance(state::Vault::LEN) + 6_000,
+            data,
+            owner: program_id,
+            executable: false,
+            rent_epoch: 0,
+        },
+    );
+
+    let mut context = program_test.start_with_context().await;
+
+    let withdraw = vault_instruction(
+        program_id,
+        &user.pubkey(),
+        VaultInstruction::Withdraw {
+            vault_name: vec![],
+            amount: 10_000,
+        },
+    );
+    let result = send(&mut context, &[withdraw], &[&user]).await;
+
+    assert_eq!(
+        custom_error(result),
+        Some(VaultError::ExceedsAvailableLamports)
+    );
+
+    let sync = vault_instruction(
+        program_id,
+        &user.pubkey(),
+        VaultInstruction::Sync { vault_name: vec![] },
+    );
+    send(&mut context, &[sync], &[&user]).await.unwrap();
+
+    let account = vault_account(&mut context, vault).await;
+    let synced = state::Vault::unpack(&account.data).unwrap();
+
+    assert_eq!(synced.balance, 6_000);
+    assert_eq!(synced.total_withdrawn, 4_000);
+}
+
+#[tokio::test]
+async fn test_withdraw_to_recipient() {
+    let program_id = Pubkey::new_unique();
+    let user = Keypair::new();
+    let recipient = Pubkey::new_unique();
+
+    let mut program_test = program_test(program_id);
+    program_test.add_account(
+        user.pubkey(),
+        Account::new(1_000_000_000, 0, &solana_program::system_program::ID),
+    );
+    program_test.add_account(
+        recipient,
+        Account::new(5_000_000, 0, &solana_program::system_program::ID),
+    );
+
+    let mut context = program_test.start_with_context().await;
+
+    let mut withdraw_to = vault_instruction(
+        program_id,
+        &user.pubkey(),
+        VaultInstruction::WithdrawTo {
+            vault_name: vec![],
+            amount: 30_000,
+        },
+    );
+    withdraw_to
+        .accounts
+        .push(AccountMeta::new(recipient, false));
+
+    let instructions = [
+        vault_instruction(
+            program_id,
+            &user.pubkey(),
+            VaultInstruction::Deposit {
+                vault_name: vec![],
+                amount: 50_000,
+            },
+        ),
+        withdraw_to,
+    ];
+    send(&mut context, &instructions, &[&user]).await.unwrap();
+
+    let account = vault_account(&mut context, vault_address(&program_id, &user.pubkey())).await;
+    let vault = state::Vault::unpack(&account.data).unwrap();
+
+    assert_eq!(vault.balance, 20_000);
+    assert_eq!(vault.total_withdrawn, 30_000);
+    assert_eq!(
+        vault_account(&mut context, recipient).await.lamports,
+        5_030_000
+    );
+}
+
+#[tokio::test]
+async fn test_sponsored_vault_creation() {
+    let program_id = Pubkey::new_unique();
+    let user = Keypair::new();
+    let sponsor = Keypair::new();
+    let vault = vault_address(&program_id, &user.pubkey());
+
+    let mut program_test = program_test(program_id);
+    program_test.add_account(
+        sponsor.pubkey(),
+        Account::new(1_000_000_000, 0, &solana_program::system_program::ID),
+    );
+
+    let mut context = program_test.start_with_context().await;
+
+    let mut create = vault_instruction(
+        program_id,
+        &user.pubkey(),
+        VaultInstruction::CreateVault {
+            vault_name: b"payroll".to_vec(),
+        },
+    );
+    create
+        .accounts
+        .push(AccountMeta::new(sponsor.pubkey(), true));
+
+    let mut create_default = vault_instruction(
+        program_id,
+        &user.pubkey(),
+        VaultInstruction::CreateVault { vault_name: vec![] },
+    );
+    create_default
+        .accounts
+        .push(AccountMeta::new(sponsor.pubkey(), true));
+
+    send(&mut context, &[create, create_default], &[&user, &sponsor])
+        .await
+        .unwrap();
+
+    let account = vault_account(&mut context, vault).await;
+    let created = state::Vault::unpack(&account.data).unwrap();
+
+    assert_eq!(created.owner, user.pubkey());
+    assert_eq!(created.balance, 0);
+    assert_eq!(
+        vault_account(&mut context, sponsor.pubkey()).await.lamports,
+        1_000_000_000 - 2 * Rent::default().minimum_balance(state::Vault::LEN),
+    );
+    assert!(context
+        .banks_client
+        .get_account(user.pubkey())
+        .await
+        .unwrap()
+        .is_none());
+
+    let mut create_again = vault_instruction(
+        program_id,
+        &user.pubkey(),
+        VaultInstruction::CreateVault { vault_name: vec![] },
+    );
+    create_again
+        .accounts
+        .push(AccountMeta::new(sponsor.pubkey(), true));
+    let result = send(&mut context, &[create_again], &[&user, &sponsor]).await;
+
+    assert_eq!(custom_error(result), Some(VaultError::VaultAlreadyExists));
+}
+
+#[tokio::test]
+async fn test_unsponsored_vault_creation() {
+    let program_id = Pubkey::new_unique();
+    let user = Keypair::new();
+    let vault = vault_address(&program_id, &user.pubkey());
+
+    let mut program_test = program_test(program_id);
+    program_test.add_account(
+        user.pubkey(),
+        Account::new(1_000_000_000, 0, &solana_program::system_program::ID),
+    );
+
+    let mut context = program_test.start_with_context().await;
+
+    let create = vault_instruction(
+        program_id,
+        &user.pubkey(),
+        VaultInstruction::CreateVault { vault_name: vec![] },
+    );
+    assert_eq!(
+        create.accounts[3].pubkey,
+        state::Config::find_address(&program_id).0
+    );
+    send(&mut context, &[create], &[&user]).await.unwrap();
+
+    let created = state::Vault::unpack(&vault_account(&mut context, vault).await.data).unwrap();
+
+    assert_eq!(created.owner, user.pubkey());
+    assert_eq!(
+        vault_account(&mut context, user.pubkey()).await.lamports,
+        1_000_000_000 - Rent::default().minimum_balance(state::Vault::LEN),
+    );
+}
+
+fn lock_instruction(
+    program_id: Pubkey,
+    user: &Pubkey,
+    lock_kind: LockKind,
+    unlock_at: u64,
+) -> Instruction {
+    vault_instruction(
+        program_id,
+        user,
+        VaultInstruction::SetLock {
+            vault_name: vec![],
+            lock_kind,
+            unlock_at,
+        },
+    )
+}
+
+#[tokio::test]
+async fn test_time_locked_withdrawals() {
+    let program_id = Pubkey::new_unique();
+    let user = Keypair::new();
+
+    let mut program_test = program_test(program_id);
+    program_test.add_account(
+        user.pubkey(),
+        Account::new(1_000_000_000, 0, &solana_program::system_program::ID),
+    );
+
+    let mut context = program_test.start_with_context().await;
+
+    let mut clock: Clock = context.banks_client.get_sysvar().await.unwrap();
+    let unlock_timestamp = clock.unix_timestamp as u64 + 3_600;
+
+    let instructions = [
+        vault_instruction(
+            program_id,
+            &user.pubkey(),
+            VaultInstruction::Deposit {
+                vault_name: vec![],
+                amount: 10_000,
+            },
+        ),
+        lock_instruction(
+            program_id,
+            &user.pubkey(),
+            LockKind::UnixTimestamp,
+            unlock_timestamp,
+        ),
+    ];
+    send(&mut context, &instructions, &[&user]).await.unwrap();
+
+    let withdraw = vault_instruction(
+        program_id,
+        &user.pubkey(),
+        VaultInstruction::Withdraw {
+            vault_name: vec![],
+            amount: 1_000,
+        },
+    );
+    let result = send(&mut context, &[withdraw], &[&user]).await;
+    assert_eq!(custom_error(result), Some(VaultError::VaultLocked));
+
+    let mut close = vault_instruction(
+        program_id,
+        &user.pubkey(),
+        VaultInstruction::Close { vault_name: vec![] },
+    );
+    close.accounts.push(AccountMeta::new(user.pubkey(), false));
+    let result = send(&mut context, &[close], &[&user]).await;
+    assert_eq!(custom_error(result), Some(VaultError::VaultLocked));
+
+    let shorten = lock_instruction(
+        program_id,
+        &user.pubkey(),
+        LockKind::UnixTimestamp,
+        unlock_timestamp - 1,
+    );
+    let result = send(&mut context, &[shorten], &[&user]).await;
+    assert_eq!(custom_error(result), Some(VaultError::LockShortened));
+
+    let extend = lock_instruction(
+        program_id,
+        &user.pubkey(),
+        LockKind::UnixTimestamp,
+        unlock_timestamp + 3_600,
+    );
+    send(&mut context, &[extend], &[&user]).await.unwrap();
+
+    clock.unix_timestamp = unlock_timestamp as i64 + 3_600;
+    context.set_sysvar(&clock);
+
+    let withdraw = vault_instruction(
+        program_id,
+        &user.pubkey(),
+        VaultInstruction::Withdraw {
+            vault_name: vec![],
+            amount: 1_000,
+        },
+    );
+    send(&mut context, &[withdraw], &[&user]).await.unwrap();
+
+    let account = vault_account(&mut context, vault_address(&program_id, &user.pubkey())).await;
+    let vault = state::Vault::unpack(&account.data).unwrap();
+
+    assert_eq!(vault.balance, 9_000);
+    assert_eq!(vault.unlock_timestamp, unlock_timestamp as i64 + 3_600);
+}
+
+#[tokio::test]
+async fn test_slot_locked_withdrawals() {
+    let program_id = Pubkey::new_unique();
+    let user = Keypair::new();
+
+    let mut program_test = program_test(program_id);
+    program_test.add_account(
+        user.pubkey(),
+        Account::new(1_000_000_000, 0, &solana_program::system_program::ID),
+    );
+
+    let mut context = program_test.start_with_context().await;
+
+    let clock: Clock = context.banks_client.get_sysvar().await.unwrap();
+    let unlock_slot = clock.slot + 1_000;
+
+    let instructions = [
+        vault_instruction(
+            program_id,
+            &user.pubkey(),
+            VaultInstruction::Deposit {
+                vault_name: vec![],
+                amount: 10_000,
+            },
+        ),
+        lock_instruction(program_id, &user.pubkey(), LockKind::Slot, unlock_slot),
+    ];
+    send(&mut context, &instructions, &[&user]).await.unwrap();
+
+    let withdraw = vault_instruction(
+        program_id,
+        &user.pubkey(),
+        VaultInstruction::Withdraw {
+            vault_name: vec![],
+            amount: 1_000,
+        },
+    );
+    let result = send(&mut context, &[withdraw], &[&user]).await;
+    assert_eq!(custom_error(result), Some(VaultError::VaultLocked));
+
+    context.warp_to_slot(unlock_slot).unwrap();
+
+    let withdraw = vault_instruction(
+        program_id,
+        &user.pubkey(),
+        VaultInstruction::Withdraw {
+            vault_name: vec![],
+            amount: 1_000,
+        },
+    );
+    send(&mut context, &[withdraw], &[&user]).await.unwrap();
+
+    // Unknown lock kind.
+    let mut malformed = lock_instruction(program_id, &user.pubkey(), LockKind::Slot, unlock_slot);
+    malformed.data[5] = 2;
+    let result = send(&mut context, &[malformed], &[&user]).await;
+
+    assert_eq!(
+        result.unwrap_err().unwrap(),
+        TransactionError::InstructionError(0, InstructionError::InvalidInstructionData),
+    );
+}
+
+fn grant_instruction(
+    program_id: Pubkey,
+    beneficiary: &Pubkey,
+    grantor: &Pubkey,
+    total: u64,
+    (start, cliff, end): (i64, i64, i64),
+    revocable: bool,
+) -> Instruction {
+    let mut instruction = vault_instruction(
+        program_id,
+        beneficiary,
+        VaultInstruction::GrantVesting {
+            vault_name: vec![],
+            amount: total,
+            terms: VestingTerms {
+                start,
+                cliff,
+                end,
+                revocable,
+            },
+        },
+    );
+    instruction.accounts.push(AccountMeta::new(*grantor, true));
+    instruction
+}
+
+fn revoke_instruction(program_id: Pubkey, beneficiary: &Pubkey, grantor: &Pubkey) -> Instruction {
+    let mut instruction = vault_instruction(
+        program_id,
+        beneficiary,
+        VaultInstruction::RevokeVesting { vault_name: vec![] },
+    );
+    instruction.accounts[0].is_signer = false;
+    instruction.accounts.push(AccountMeta::new(*grantor, true));
+    instruction
+}
+
+#[tokio::test]
+async fn test_vesting_grant_and_revoke() {
+    let program_id = Pubkey::new_unique();
+    let beneficiary = Keypair::new();
+    let grantor = Keypair::new();
+    let vault = vault_address(&program_id, &beneficiary.pubkey());
+
+    let mut program_test = program_test(program_id);
+    for account in [&beneficiary, &grantor] {
+        program_test.add_account(
+            account.pubkey(),
+            Account::new(1_000_000_000, 0, &solana_program::system_program::ID),
+        );
+    }
+
+    let mut context = program_test.start_with_context().await;
+
+    let mut clock: Clock = context.banks_client.get_sysvar().await.unwrap();
+    let start = clock.unix_timestamp;
+    let schedule = (start, start + 100, start + 1_000);
+
+    let grant = grant_instruction(
+        program_id,
+        &beneficiary.pubkey(),
+        &grantor.pubkey(),
+        1_000_000,
+        schedule,
+        true,
+    );
+    send(&mut context, &[grant], &[&beneficiary, &grantor])
+        .await
+        .unwrap();
+
+    let withdraw = vault_instruction(
+        program_id,
+        &beneficiary.pubkey(),
+        VaultInstruction::Withdraw {
+            vault_name: vec![],
+            amount: 1,
+        },
+    );
+    let result = send(&mut context, &[withdraw], &[&beneficiary]).await;
+    assert_eq!(
+        custom_error(result),
+        Some(VaultError::InsufficientVestedBalance)
+    );
+
+    let mut close = vault_instruction(
+        program_id,
+        &beneficiary.pubkey(),
+        VaultInstruction::Close { vault_name: vec![] },
+    );
+    close
+        .accounts
+        .push(AccountMeta::new(beneficiary.pubkey(), false));
+    let result = send(&mut context, &[close], &[&beneficiary]).await;
+    assert_eq!(custom_error(result), Some(VaultError::VestingActive));
+
+    let grant_again = grant_instruction(
+        program_id,
+        &beneficiary.pubkey(),
+        &grantor.pubkey(),
+        1_000,
+        schedule,
+        true,
+    );
+    let result = send(&mut context, &[grant_again], &[&beneficiary, &grantor]).await;
+    assert_eq!(custom_error(result), Some(VaultError::VestingActive));
+
+    clock.unix_timestamp = start + 500;
+    context.set_sysvar(&clock);
+
+    let withdraw = vault_instruction(
+        program_id,
+        &beneficiary.pubkey(),
+        VaultInstruction::Withdraw {
+            vault_name: vec![],
+            amount: 500_000,
+        },
+    );
+    send(&mut context, &[withdraw], &[&beneficiary])
+        .await
+        .unwrap();
+
+    let withdraw = vault_instruction(
+        program_id,
+        &beneficiary.pubkey(),
+        VaultInstruction::Withdraw {
+            vault_name: vec![],
+            amount: 1,
+        },
+    );
+    let result = send(&mut context, &[withdraw], &[&beneficiary]).await;
+    assert_eq!(
+        custom_error(result),
+        Some(VaultError::InsufficientVestedBalance)
+    );
+
+    let revoke = revoke_instruction(program_id, &beneficiary.pubkey(), &beneficiary.pubkey());
+    let result = send(&mut context, &[revoke], &[&beneficiary]).await;
+    assert_eq!(
+        custom_error(result),
+        Some(VaultError::InvalidVestingGrantor)
+    );
+
+    let grantor_lamports = vault_account(&mut context, grantor.pubkey()).await.lamports;
+
+    let revoke = revoke_instruction(program_id, &beneficiary.pubkey(), &grantor.pubkey());
+    send(&mut context, &[revoke], &[&grantor]).await.unwrap();
+
+    let account = vault_account(&mut context, vault).await;
+    let revoked = state::Vault::unpack(&account.data).unwrap();
+
+    assert_eq!(revoked.balance, 0);
+    assert_eq!(revoked.total_deposited, 1_000_000);
+    assert_eq!(revoked.total_withdrawn, 1_000_000);
+    assert_eq!(revoked.vesting, state::VestingSchedule::default());
+    assert_eq!(
+        vault_account(&mut context, grantor.pubkey()).await.lamports,
+        grantor_lamports + 500_000,
+    );
+}
+
+#[tokio::test]
+async fn test_irrevocable_vesting() {
+    let program_id = Pubkey::new_unique();
+    let beneficiary = Keypair::new();
+    let grantor = Keypair::new();
+
+    let mut program_test = program_test(program_id);
+    program_test.add_account(
+        grantor.pubkey(),
+        Account::new(1_000_000_000, 0, &solana_program::system_program::ID),
+    );
+
+    let mut context = program_test.start_with_context().await;
+
+    let mut clock: Clock = context.banks_client.get_sysvar().await.unwrap();
+    let start = clock.unix_timestamp;
+
+    let grant = grant_instruction(
+        program_id,
+        &beneficiary.pubkey(),
+        &grantor.pubkey(),
+        1_000_000,
+        (start, start, start + 1_000),
+        false,
+    );
+    send(&mut context, &[grant], &[&beneficiary, &grantor])
+        .await
+        .unwrap();
+
+    let revoke = revoke_instruction(program_id, &beneficiary.pubkey(), &grantor.pubkey());
+    let result = send(&mut context, &[revoke], &[&grantor]).await;
+    assert_eq!(custom_error(result), Some(VaultError::VestingNotRevocable));
+
+    clock.unix_timestamp = start + 1_000;
+    context.set_sysvar(&clock);
+
+    let mut withdraw_to = vault_instruction(
+        program_id,
+        &beneficiary.pubkey(),
+        VaultInstruction::WithdrawTo {
+            vault_name: vec![],
+            amount: 1_000_000,
+        },
+    );
+    withdraw_to
+        .accounts
+        .push(AccountMeta::new(grantor.pubkey(), false));
+    send(&mut context, &[withdraw_to], &[&beneficiary])
+        .await
+        .unwrap();
+
+    let inverted = grant_instruction(
+        program_id,
+        &beneficiary.pubkey(),
+        &grantor.pubkey(),
+        1_000,
+        (start + 1_000, start, start + 2_000),
+        false,
+    );
+    let result = send(&mut context, &[inverted], &[&beneficiary, &grantor]).await;
+    assert_eq!(
+        custom_error(result),
+        Some(VaultError::InvalidVestingSchedule)
+    );
+}
+
+fn stream_instruction(
+    program_id: Pubkey,
+    sender: &Pubkey,
+    recipient: &Pubkey,
+    instruction: VaultInstruction,
+) -> Instruction {
+    let stream_id = match instruction {
+        VaultInstruction::OpenStream { stream_id, .. }
+        | VaultInstruction::ClaimStream { stream_id, .. }
+        | VaultInstruction::CancelStream { stream_id, .. } => stream_id,
+        _ => panic!("not a stream instruction: {instruction:?}"),
+    };
+
+    let mut instruction = vault_instruction(program_id, sender, instruction);
+    instruction.accounts.extend([
+        AccountMeta::new(*recipient, false),
+        AccountMeta::new(
+            state::Stream::find_address(&program_id, sender, recipient, stream_id).0,
+            false,
+        ),
+    ]);
+    instruction
+}
+
+#[tokio::test]
+async fn test_stream_claim_and_cancel() {
+    let program_id = Pubkey::new_unique();
+    let sender = Keypair::new();
+    let recipient = Keypair::new();
+    let vault = vault_address(&program_id, &sender.pubkey());
+    let stream =
+        state::Stream::find_address(&program_id, &sender.pubkey(), &recipient.pubkey(), 1).0;
+
+    let mut program_test = program_test(program_id);
+    for account in [&sender, &recipient] {
+        program_test.add_account(
+            account.pubkey(),
+            Account::new(1_000_000_000, 0, &solana_program::system_program::ID),
+        );
+    }
+
+    let mut context = program_test.start_with_context().await;
+
+    let mut clock: Clock = context.banks_client.get_sysvar().await.unwrap();
+    let start = clock.unix_timestamp;
+
+    let instructions = [
+        vault_instruction(
+            program_id,
+            &sender.pubkey(),
+            VaultInstruction::Deposit {
+                vault_name: vec![],
+                amount: 1_000_000,
+            },
+        ),
+        stream_instruction(
+            program_id,
+            &sender.pubkey(),
+            &recipient.pubkey(),
+            VaultInstruction::OpenStream {
+                vault_name: vec![],
+                amount: 600_000,
+                stream_id: 1,
+                rate: 1_000,
+            },
+        ),
+    ];
+    send(&mut context, &instructions, &[&sender]).await.unwrap();
+
+    let account = vault_account(&mut context, stream).await;
+    let opened = state::Stream::unpack(&account.data).unwrap();
+
+    assert_eq!(opened.vault, vault);
+    assert_eq!(opened.deposited, 600_000);
+    assert_eq!(opened.rate, 1_000);
+    assert_eq!(
+        state::Vault::unpack(&vault_account(&mut context, vault).await.data)
+            .unwrap()
+            .balance,
+        400_000,
+    );
+
+    let open_again = stream_instruction(
+        program_id,
+        &sender.pubkey(),
+        &recipient.pubkey(),
+        VaultInstruction::OpenStream {
+            vault_name: vec![],
+            amount: 1_000,
+            stream_id: 1,
+            rate: 1_000,
+        },
+    );
+    let result = send(&mut context, &[open_again], &[&sender]).await;
+    assert_eq!(custom_error(result), Some(VaultError::StreamAlreadyExists));
+
+    clock.unix_timestamp = start + 100;
+    context.set_sysvar(&clock);
+
+    let mut claim = stream_instruction(
+        program_id,
+        &sender.pubkey(),
+        &recipient.pubkey(),
+        VaultInstruction::ClaimStream {
+            vault_name: vec![],
+            stream_id: 1,
+        },
+    );
+    claim.accounts[0].is_signer = false;
+    claim.accounts[4].is_signer = true;
+    send(&mut context, &[claim], &[&recipient]).await.unwrap();
+
+    assert_eq!(
+        vault_account(&mut context, recipient.pubkey())
+            .await
+            .lamports,
+        1_000_000_000 + 100_000,
+    );
+
+    let impostor = Keypair::new();
+    let mut claim = stream_instruction(
+        program_id,
+        &sender.pubkey(),
+        &impostor.pubkey(),
+        VaultInstruction::ClaimStream {
+            vault_name: vec![],
+            stream_id: 1,
+        },
+    );
+    claim.accounts[0].is_signer = false;
+    claim.accounts[4].is_signer = true;
+    claim.accounts[5].pubkey = stream;
+    let result = send(&mut context, &[claim], &[&impostor]).await;
+    assert_eq!(custom_error(result), Some(VaultError::InvalidStreamAddress));
+
+    clock.unix_timestamp = start + 250;
+    context.set_sysvar(&clock);
+
+    let sender_lamports = vault_account(&mut context, sender.pubkey()).await.lamports;
+
+    let cancel = stream_instruction(
+        program_id,
+        &sender.pubkey(),
+        &recipient.pubkey(),
+        VaultInstruction::CancelStream {
+            vault_name: vec![],
+            stream_id: 1,
+        },
+    );
+    send(&mut context, &[cancel], &[&sender]).await.unwrap();
+
+    let cancelled = state::Vault::unpack(&vault_account(&mut context, vault).await.data).unwrap();
+
+    assert_eq!(cancelled.balance, 750_000);
+    assert_eq!(cancelled.total_withdrawn, 600_000);
+    assert_eq!(cancelled.total_deposited, 1_350_000);
+    assert_eq!(
+        vault_account(&mut context, recipient.pubkey())
+            .await
+            .lamports,
+        1_000_000_000 + 250_000,
+    );
+    assert_eq!(
+        vault_account(&mut context, sender.pubkey()).await.lamports,
+        sender_lamports + Rent::default().minimum_balance(state::Stream::LEN),
+    );
+    assert!(context
+        .banks_client
+        .get_account(stream)
+        .await
+        .unwrap()
+        .is_none());
+}
+
+#[tokio::test]
+async fn test_stream_cancelled_by_recipient() {
+    let program_id = Pubkey::new_unique();
+    let sender = Keypair::new();
+    let recipient = Keypair::new();
+    let vault = vault_address(&program_id, &sender.pubkey());
+
+    let mut program_test = program_test(program_id);
+    program_test.add_account(
+        sender.pubkey(),
+        Account::new(1_000_000_000, 0, &solana_program::system_program::ID),
+    );
+
+    let mut context = program_test.start_with_context().await;
+
+    let instructions = [
+        vault_instruction(
+            program_id,
+            &sender.pubkey(),
+            VaultInstruction::Deposit {
+                vault_name: vec![],
+                amount: 1_000_000,
+            },
+        ),
+        stream_instruction(
+            program_id,
+            &sender.pubkey(),
+            &recipient.pubkey(),
+            VaultInstruction::OpenStream {
+                vault_name: vec![],
+                amount: 600_000,
+                stream_id: 7,
+                rate: 1,
+            },
+        ),
+    ];
+    send(&mut context, &instructions, &[&sender]).await.unwrap();
+
+    let mut cancel = stream_instruction(
+        program_id,
+        &sender.pubkey(),
+        &recipient.pubkey(),
+        VaultInstruction::CancelStream {
+            vault_name: vec![],
+            stream_id: 7,
+        },
+    );
+    cancel.accounts[0].is_signer = false;
+    let result = send(&mut context, &[cancel.clone()], &[]).await;
+    assert_eq!(custom_error(result), Some(VaultError::MissingSignature));
+
+    cancel.accounts[4].is_signer = true;
+    send(&mut context, &[cancel], &[&recipient]).await.unwrap();
+
+    let vault = state::Vault::unpack(&vault_account(&mut context, vault).await.data).unwrap();
+
+    assert_eq!(vault.balance, 1_000_000);
+}
+
+fn proposal_instruction(
+    program_id: Pubkey,
+    creator: &Pubkey,
+    owner: &Pubkey,
+    instruction: VaultInstruction,
+    proposal_id: u64,
+    destination: Option<&Pubkey>,
+) -> Instruction {
+    let vault = vault_address(&program_id, creator);
+
+    let mut instruction = vault_instruction(program_id, creator, instruction);
+    instruction.accounts[0].is_signer = false;
+    instruction.accounts.extend([
+        AccountMeta::new(state::Multisig::find_address(&program_id, &vault).0, false),
+        AccountMeta::new(
+            state::Proposal::find_address(&program_id, &vault, proposal_id).0,
+            false,
+        ),
+        AccountMeta::new(*owner, true),
+    ]);
+
+    if let Some(destination) = destination {
+        instruction
+            .accounts
+            .push(AccountMeta::new(*destination, false));
+    }
+
+    instruction
+}
+
+#[tokio::test]
+async fn test_multisig_withdrawal() {
+    let program_id = Pubkey::new_unique();
+    let creator = Keypair::new();
+    let owners = [Keypair::new(), Keypair::new(), Keypair::new()];
+    let outsider = Keypair::new();
+    let destination = outsider.pubkey();
+    let vault = vault_address(&program_id, &creator.pubkey());
+
+    let mut program_test = program_test(program_id);
+    for account in [&creator, &owners[0], &outsider] {
+        program_test.add_account(
+            account.pubkey(),
+            Account::new(1_000_000_000, 0, &solana_program::system_program::ID),
+        );
+    }
+
+    let mut context = program_test.start_with_context().await;
+
+    let mut clock: Clock = context.banks_client.get_sysvar().await.unwrap();
+
+    let mut enable = vault_instruction(
+        program_id,
+        &creator.pubkey(),
+        VaultInstruction::EnableMultisig {
+            vault_name: vec![],
+            threshold: 2,
+            owners: owners.iter().map(Signer::pubkey).collect(),
+        },
+    );
+    // Accounts past the multisig PDA are not owners.
+    enable.accounts.extend([
+        AccountMeta::new(state::Multisig::find_address(&program_id, &vault).0, false),
+        AccountMeta::new_readonly(outsider.pubkey(), false),
+    ]);
+
+    let instructions = [
+        vault_instruction(
+            program_id,
+            &creator.pubkey(),
+            VaultInstruction::Deposit {
+                vault_name: vec![],
+                amount: 1_000_000,
+            },
+        ),
+        enable,
+    ];
+    send(&mut context, &instructions, &[&creator])
+        .await
+        .unwrap();
+
+    let withdraw = vault_instruction(
+        program_id,
+        &creator.pubkey(),
+        VaultInstruction::Withdraw {
+            vault_name: vec![],
+            amount: 1,
+        },
+    );
+    let result = send(&mut context, &[withdraw], &[&creator]).await;
+    assert_eq!(custom_error(result), Some(VaultError::MultisigRequired));
+
+    let expires_at = clock.unix_timestamp + 3_600;
+    let propose = |owner: &Keypair, proposal_id| {
+        proposal_instruction(
+            program_id,
+            &creator.pubkey(),
+            &owner.pubkey(),
+            VaultInstruction::Propose {
+                vault_name: vec![],
+                amount: 300_000,
+                expires_at,
+            },
+            proposal_id,
+            Some(&destination),
+        )
+    };
+    let approve = |owner: &Keypair, proposal_id| {
+        proposal_instruction(
+            program_id,
+            &creator.pubkey(),
+            &owner.pubkey(),
+            VaultInstruction::Approve {
+                vault_name: vec![],
+                proposal_id,
+            },
+            proposal_id,
+            None,
+        )
+    };
+    let execute = |owner: &Keypair, proposal_id| {
+        proposal_instruction(
+            program_id,
+            &creator.pubkey(),
+            &owner.pubkey(),
+            VaultInstruction::Execute {
+                vault_name: vec![],
+                proposal_id,
+            },
+            proposal_id,
+            Some(&destination),
+        )
+    };
+
+    let result = send(&mut context, &[propose(&outsider, 0)], &[&outsider]).await;
+    assert_eq!(custom_error(result), Some(VaultError::NotMultisigOwner));
+
+    send(&mut context, &[propose(&owners[0], 0)], &[&owners[0]])
+        .await
+        .unwrap();
+
+    let result = send(&mut context, &[execute(&owners[0], 0)], &[&owners[0]]).await;
+    assert_eq!(
+        custom_error(result),
+        Some(VaultError::InsufficientApprovals)
+    );
+
+    let result = send(&mut context, &[approve(&outsider, 0)], &[&outsider]).await;
+    assert_eq!(custom_error(result), Some(VaultError::NotMultisigOwner));
+
+    send(
+        &mut context,
+        &[approve(&owners[1], 0), execute(&owners[2], 0)],
+        &[&owners[1], &owners[2]],
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(
+        vault_account(&mut context, destination).await.lamports,
+        1_000_000_000 + 300_000,
+    );
+
+    let result = send(&mut context, &[execute(&owners[0], 0)], &[&owners[0]]).await;
+    assert_eq!(custom_error(result), Some(VaultError::ProposalExecuted));
+
+    let result = send(&mut context, &[propose(&owners[0], 0)], &[&owners[0]]).await;
+    assert_eq!(
+        custom_error(result),
+        Some(VaultError::InvalidProposalAddress)
+    );
+
+    send(
+        &mut context,
+        &[propose(&owners[0], 1), approve(&owners[1], 1)],
+        &[&owners[0], &owners[1]],
+    )
+    .await
+    .unwrap();
+
+    clock.unix_timestamp = expires_at;
+    context.set_sysvar(&clock);
+
+    let result = send(&mut context, &[execute(&owners[0], 1)], &[&owners[0]]).await;
+    assert_eq!(custom_error(result), Some(VaultError::ProposalExpired));
+
+    let vault = state::Vault::unpack(&vault_account(&mut context, vault).await.data).unwrap();
+
+    assert!(vault.multisig);
+    assert_eq!(vault.balance, 700_000);
+}
+
+#[tokio::test]
+async fn test_multisig_rejects_tokens() {
+    let program_id = Pubkey::new_unique();
+    let user = Keypair::new();
+    let vault = vault_address(&program_id, &user.pubkey());
+
+    let mut program_test = program_test(program_id);
+    program_test.add_account(
+        user.pubkey(),
+        Account::new(1_000_000_000, 0, &solana_program::system_program::ID),
+    );
+
+    let mut context = program_test.start_with_context().await;
+
+    let mint = create_mint(
+        &mut context,
+        &user.pubkey(),
+        1_000,
+        &spl_token::id(),
+        &[],
+        |_| vec![],
+    )
+    .await;
+    let token_transfer = |instruction| {
+        token_instruction(
+            program_id,
+            &user.pubkey(),
+            &mint,
+            &spl_token::id(),
+            instruction,
+        )
+    };
+    let mut enable = vault_instruction(
+        program_id,
+        &user.pubkey(),
+        VaultInstruction::EnableMultisig {
+            vault_name: vec![],
+            threshold: 1,
+            owners: vec![user.pubkey()],
+        },
+    );
+    enable.accounts.push(AccountMeta::new(
+        state::Multisig::find_address(&program_id, &vault).0,
+        false,
+    ));
+
+    send(
+        &mut context,
+        &[token_transfer(VaultInstruction::DepositToken {
+            vault_name: vec![],
+            amount: 400,
+        })],
+        &[&user],
+    )
+    .await
+    .unwrap();
+
+    let result = send(&mut context, std::slice::from_ref(&enable), &[&user]).await;
+    assert_eq!(custom_error(result), Some(VaultError::TokensHeld));
+
+    send(
+        &mut context,
+        &[token_transfer(VaultInstruction::WithdrawToken {
+            vault_name: vec![],
+            amount: 400,
+        })],
+        &[&user],
+    )
+    .await
+    .unwrap();
+    send(&mut context, &[enable], &[&user]).await.unwrap();
+
+    let result = send(
+        &mut context,
+        &[token_transfer(VaultInstruction::DepositToken {
+            vault_name: vec![],
+            amount: 100,
+        })],
+        &[&user],
+    )
+    .await;
+    assert_eq!(custom_error(result), Some(VaultError::MultisigRequired));
+}
+
+fn delegate_instruction(
+    program_id: Pubkey,
+    owner: &Pubkey,
+    delegate: &Pubkey,
+    instruction: VaultInstruction,
+) -> Instruction {
+    let vault = vault_address(&program_id, owner);
+
+    let mut instruction = vault_instruction(program_id, owner, instruction);
+    instruction.accounts.extend([
+        AccountMeta::new(*delegate, false),
+        AccountMeta::new(
+            state::Allowance::find_address(&program_id, &vault, delegate).0,
+            false,
+        ),
+    ]);
+    instruction
+}
+
+fn delegate_withdraw_instruction(
+    program_id: Pubkey,
+    owner: &Pubkey,
+    delegate: &Pubkey,
+    amount: u64,
+) -> Instruction {
+    let mut instruction = delegate_instruction(
+        program_id,
+        owner,
+        delegate,
+        VaultInstruction::Withdraw {
+            vault_name: vec![],
+            amount,
+        },
+    );
+    instruction.accounts[0].is_signer = false;
+    instruction.accounts[4].is_signer = true;
+    instruction
+}
+
+#[tokio::test]
+async fn test_delegate_allowance() {
+    let program_id = Pubkey::new_unique();
+    let owner = Keypair::new();
+    let delegate = Keypair::new();
+    let vault = vault_address(&program_id, &owner.pubkey());
+    let allowance = state::Allowance::find_address(&program_id, &vault, &delegate.pubkey()).0;
+
+    let mut program_test = program_test(program_id);
+    for account in [&owner, &delegate] {
+        program_test.add_account(
+            account.pubkey(),
+            Account::new(1_000_000_000, 0, &solana_program::system_program::ID),
+        );
+    }
+
+    let mut context = program_test.start_with_context().await;
+
+    let clock: Clock = context.banks_client.get_sysvar().await.unwrap();
+    let expiry_slot = clock.slot + 1_000;
+
+    let approve = delegate_instruction(
+        program_id,
+        &owner.pubkey(),
+        &delegate.pubkey(),
+        VaultInstruction::ApproveDelegate {
+            vault_name: vec![],
+            amount: 100_000,
+            expiry_slot,
+        },
+    );
+
+    let instructions = [
+        vault_instruction(
+            program_id,
+            &owner.pubkey(),
+            VaultInstruction::Deposit {
+                vault_name: vec![],
+                amount: 1_000_000,
+            },
+        ),
+        approve,
+    ];
+    send(&mut context, &instructions, &[&owner]).await.unwrap();
+
+    let withdraw =
+        delegate_withdraw_instruction(program_id, &owner.pubkey(), &delegate.pubkey(), 60_000);
+    send(&mut context, &[withdraw], &[&delegate]).await.unwrap();
+
+    let account = vault_account(&mut context, allowance).await;
+    assert_eq!(
+        state::Allowance::unpack(&account.data).unwrap().amount,
+        40_000
+    );
+    assert_eq!(
+        vault_account(&mut context, delegate.pubkey())
+            .await
+            .lamports,
+        1_000_000_000 + 60_000,
+    );
+
+    let withdraw =
+        delegate_withdraw_instruction(program_id, &owner.pubkey(), &delegate.pubkey(), 50_000);
+    let result = send(&mut context, &[withdraw], &[&delegate]).await;
+    assert_eq!(
+        custom_error(result),
+        Some(VaultError::InsufficientAllowance)
+    );
+
+    let stranger = Keypair::new();
+    let mut withdraw =
+        delegate_withdraw_instruction(program_id, &owner.pubkey(), &stranger.pubkey(), 1);
+    withdraw.accounts[5].pubkey = allowance;
+    let result = send(&mut context, &[withdraw], &[&stranger]).await;
+    assert_eq!(
+        custom_error(result),
+        Some(VaultError::InvalidAllowanceAddress)
+    );
+
+    let mut withdraw = vault_instruction(
+        program_id,
+        &owner.pubkey(),
+        VaultInstruction::Withdraw {
+            vault_name: vec![],
+            amount: 1,
+        },
+    );
+    withdraw.accounts[0].is_signer = false;
+    let result = send(&mut context, &[withdraw], &[]).await;
+    assert_eq!(custom_error(result), Some(VaultError::MissingSignature));
+
+    context.warp_to_slot(expiry_slot).unwrap();
+
+    let withdraw =
+        delegate_withdraw_instruction(program_id, &owner.pubkey(), &delegate.pubkey(), 1);
+    let result = send(&mut context, &[withdraw], &[&delegate]).await;
+    assert_eq!(custom_error(result), Some(VaultError::AllowanceExpired));
+
+    let revoke = delegate_instruction(
+        program_id,
+        &owner.pubkey(),
+        &delegate.pubkey(),
+        VaultInstruction::RevokeDelegate { vault_name: vec![] },
+    );
+    send(&mut context, &[revoke], &[&owner]).await.unwrap();
+
+    assert!(context
+        .banks_client
+        .get_account(allowance)
+        .await
+        .unwrap()
+        .is_none());
+
+    let vault = state::Vault::unpack(&vault_account(&mut context, vault).await.data).unwrap();
+
+    assert_eq!(vault.balance, 940_000);
+}
+
+#[tokio::test]
+async fn test_withdrawal_limit() {
+    let program_id = Pubkey::new_unique();
+    let user = Keypair::new();
+
+    let mut program_test = program_test(program_id);
+    program_test.add_account(
+        user.pubkey(),
+        Account::new(1_000_000_000, 0, &solana_program::system_program::ID),
+    );
+
+    let mut context = program_test.start_with_context().await;
+
+    let mut clock: Clock = context.banks_client.get_sysvar().await.unwrap();
+    let start = clock.unix_timestamp;
+
+    let instructions = [
+        vault_instruction(
+            program_id,
+            &user.pubkey(),
+            VaultInstruction::Deposit {
+                vault_name: vec![],
+                amount: 1_000_000,
+            },
+        ),
+        vault_instruction(
+            program_id,
+            &user.pubkey(),
+            VaultInstruction::SetWithdrawalLimit {
+                vault_name: vec![],
+                limit: 100_000,
+            },
+        ),
+        vault_instruction(
+            program_id,
+            &user.pubkey(),
+            VaultInstruction::Withdraw {
+                vault_name: vec![],
+                amount: 60_000,
+            },
+        ),
+    ];
+    send(&mut context, &instructions, &[&user]).await.unwrap();
+
+    let withdraw = vault_instruction(
+        program_id,
+        &user.pubkey(),
+        VaultInstruction::Withdraw {
+            vault_name: vec![],
+            amount: 50_000,
+        },
+    );
+    let result = send(&mut context, &[withdraw], &[&user]).await;
+    assert_eq!(
+        custom_error(result),
+        Some(VaultError::WithdrawalLimitExceeded)
+    );
+
+    let mut close = vault_instruction(
+        program_id,
+        &user.pubkey(),
+        VaultInstruction::Close { vault_name: vec![] },
+    );
+    close.accounts.push(AccountMeta::new(user.pubkey(), false));
+    let result = send(&mut context, &[close], &[&user]).await;
+    assert_eq!(
+        custom_error(result),
+        Some(VaultError::WithdrawalLimitExceeded)
+    );
+
+    clock.unix_timestamp = start + state::WITHDRAWAL_LIMIT_WINDOW / 2;
+    context.set_sysvar(&clock);
+
+    let loosen = vault_instruction(
+        program_id,
+        &user.pubkey(),
+        VaultInstruction::SetWithdrawalLimit {
+            vault_name: vec![],
+            limit: 0,
+        },
+    );
+    send(&mut context, &[loosen], &[&user]).await.unwrap();
+
+    clock.unix_timestamp = start + state::WITHDRAWAL_LIMIT_WINDOW;
+    context.set_sysvar(&clock);
+
+    let withdraw = vault_instruction(
+        program_id,
+        &user.pubkey(),
+        VaultInstruction::Withdraw {
+            vault_name: vec![],
+            amount: 100_000,
+        },
+    );
+    send(&mut context, &[withdraw], &[&user]).await.unwrap();
+
+    let withdraw = vault_instruction(
+        program_id,
+        &user.pubkey(),
+        VaultInstruction::Withdraw {
+            vault_name: vec![],
+            amount: 1,
+        },
+    );
+    let result = send(&mut context, &[withdraw], &[&user]).await;
+    assert_eq!(
+        custom_error(result),
+        Some(VaultError::WithdrawalLimitExceeded)
+    );
+
+    clock.unix_timestamp =
+        start + state::WITHDRAWAL_LIMIT_WINDOW / 2 + state::WITHDRAWAL_LIMIT_DELAY;
+    context.set_sysvar(&clock);
+
+    let withdraw = vault_instruction(
+        program_id,
+        &user.pubkey(),
+        VaultInstruction::Withdraw {
+            vault_name: vec![],
+            amount: 500_000,
+        },
+    );
+    send(&mut context, &[withdraw], &[&user]).await.unwrap();
+
+    let vault = state::Vault::unpack(
+        &vault_account(&mut context, vault_address(&program_id, &user.pubkey()))
+            .await
+            .data,
+    )
+    .unwrap();
+
+    assert_eq!(vault.balance, 340_000);
+    assert_eq!(vault.withdrawal_limit, state::WithdrawalLimit::default());
+}
+
+fn allowlist_instruction(
+    program_id: Pubkey,
+    owner: &Pubkey,
+    instruction: VaultInstruction,
+    destination: &Pubkey,
+) -> Instruction {
+    let mut instruction = vault_instruction(program_id, owner, instruction);
+    instruction.accounts.extend([
+        allowlist_meta(program_id, owner),
+        AccountMeta::new_readonly(*destination, false),
+    ]);
+    instruction
+}
+
+fn allowlist_meta(program_id: Pubkey, owner: &Pubkey) -> AccountMeta {
+    let vault = vault_address(&program_id, owner);
+
+    AccountMeta::new(state::Allowlist::find_address(&program_id, &vault).0, false)
+}
+
+#[tokio::test]
+async fn test_destination_allowlist() {
+    let program_id = Pubkey::new_unique();
+    let owner = Keypair::new();
+    let recipient = Keypair::new();
+
+    let mut program_test = program_test(program_id);
+    for account in [&owner, &recipient] {
+        program_test.add_account(
+            account.pubkey(),
+            Account::new(1_000_000_000, 0, &solana_program::system_program::ID),
+        );
+    }
+
+    let mut context = program_test.start_with_context().await;
+
+    let mut clock: Clock = context.banks_client.get_sysvar().await.unwrap();
+    let start = clock.unix_timestamp;
+
+    let instructions = [
+        vault_instruction(
+            program_id,
+            &owner.pubkey(),
+            VaultInstruction::Deposit {
+                vault_name: vec![],
+                amount: 1_000_000,
+            },
+        ),
+        allowlist_instruction(
+            program_id,
+            &owner.pubkey(),
+            VaultInstruction::AddDestination { vault_name: vec![] },
+            &owner.pubkey(),
+        ),
+        vault_instruction(
+            program_id,
+            &owner.pubkey(),
+            VaultInstruction::SetAllowlist {
+                vault_name: vec![],
+                enabled: true,
+            },
+        ),
+    ];
+    send(&mut context, &instructions, &[&owner]).await.unwrap();
+
+    let withdraw_to = |amount| {
+        let mut instruction = vault_instruction(
+            program_id,
+            &owner.pubkey(),
+            VaultInstruction::WithdrawTo {
+                vault_name: vec![],
+                amount,
+            },
+        );
+        instruction.accounts.extend([
+            AccountMeta::new(recipient.pubkey(), false),
+            allowlist_meta(program_id, &owner.pubkey()),
+        ]);
+        instruction
+    };
+
+    let result = send(&mut context, &[withdraw_to(1_000)], &[&owner]).await;
+    assert_eq!(
+        custom_error(result),
+        Some(VaultError::DestinationNotAllowed)
+    );
+
+    let withdraw = vault_instruction(
+        program_id,
+        &owner.pubkey(),
+        VaultInstruction::Withdraw {
+            vault_name: vec![],
+            amount: 1_000,
+        },
+    );
+    let result = send(&mut context, &[withdraw], &[&owner]).await;
+    assert_eq!(
+        custom_error(result),
+        Some(VaultError::InvalidAllowlistAddress)
+    );
+
+    let mut withdraw = vault_instruction(
+        program_id,
+        &owner.pubkey(),
+        VaultInstruction::Withdraw {
+            vault_name: vec![],
+            amount: 1_000,
+        },
+    );
+    withdraw
+        .accounts
+        .push(allowlist_meta(program_id, &owner.pubkey()));
+    send(&mut context, &[withdraw], &[&owner]).await.unwrap();
+
+    let add = allowlist_instruction(
+        program_id,
+        &owner.pubkey(),
+        VaultInstruction::AddDestination { vault_name: vec![] },
+        &recipient.pubkey(),
+    );
+    send(&mut context, &[add], &[&owner]).await.unwrap();
+
+    let result = send(&mut context, &[withdraw_to(2_000)], &[&owner]).await;
+    assert_eq!(
+        custom_error(result),
+        Some(VaultError::DestinationNotAllowed)
+    );
+
+    let disable = vault_instruction(
+        program_id,
+        &owner.pubkey(),
+        VaultInstruction::SetAllowlist {
+            vault_name: vec![],
+            enabled: false,
+        },
+    );
+    send(&mut context, &[disable], &[&owner]).await.unwrap();
+
+    let remove = allowlist_instruction(
+        program_id,
+        &owner.pubkey(),
+        VaultInstruction::RemoveDestination { vault_name: vec![] },
+        &owner.pubkey(),
+    );
+    send(&mut context, &[remove], &[&owner]).await.unwrap();
+
+    let mut withdraw = vault_instruction(
+        program_id,
+        &owner.pubkey(),
+        VaultInstruction::Withdraw {
+            vault_name: vec![],
+            amount: 1_000,
+        },
+    );
+    withdraw
+        .accounts
+        .push(allowlist_meta(program_id, &owner.pubkey()));
+    let result = send(&mut context, &[withdraw], &[&owner]).await;
+    assert_eq!(
+        custom_error(result),
+        Some(VaultError::DestinationNotAllowed)
+    );
+
+    clock.unix_timestamp = start + state::ALLOWLIST_COOLDOWN;
+    context.set_sysvar(&clock);
+
+    send(&mut context, &[withdraw_to(3_000)], &[&owner])
+        .await
+        .unwrap();
+
+    let withdraw = vault_instruction(
+        program_id,
+        &owner.pubkey(),
+        VaultInstruction::Withdraw {
+            vault_name: vec![],
+            amount: 4_000,
+        },
+    );
+    send(&mut context, &[withdraw], &[&owner]).await.unwrap();
+
+    assert_eq!(
+        vault_account(&mut context, recipient.pubkey())
+            .await
+            .lamports,
+        1_000_000_000 + 3_000,
+    );
+
+    let vault = state::Vault::unpack(
+        &vault_account(&mut context, vault_address(&program_id, &owner.pubkey()))
+            .await
+            .data,
+    )
+    .unwrap();
+
+    assert!(!vault.allowlist_active(clock.unix_timestamp));
+    assert_eq!(vault.balance, 992_000);
+}
+
+#[tokio::test]
+async fn test_guardian_recovery() {
+    let program_id = Pubkey::new_unique();
+    let owner = Keypair::new();
+    let guardians = [Keypair::new(), Keypair::new(), Keypair::new()];
+    let new_authority = Keypair::new();
+    let vault = vault_address(&program_id, &owner.pubkey());
+    let guardians_address = state::Guardians::find_address(&program_id, &vault).0;
+
+    let mut program_test = program_test(program_id);
+    for account in [&owner, &new_authority] {
+        program_test.add_account(
+            account.pubkey(),
+            Account::new(1_000_000_000, 0, &solana_program::system_program::ID),
+        );
+    }
+
+    let mut context = program_test.start_with_context().await;
+
+    let mut clock: Clock = context.banks_client.get_sysvar().await.unwrap();
+    let start = clock.unix_timestamp;
+
+    let mut set_guardians = vault_instruction(
+        program_id,
+        &owner.pubkey(),
+        VaultInstruction::SetGuardians {
+            vault_name: vec![],
+            threshold: 2,
+            guardians: guardians.iter().map(Signer::pubkey).collect(),
+        },
+    );
+    // Accounts past the guardians PDA are not guardians.
+    set_guardians.accounts.extend([
+        AccountMeta::new(guardians_address, false),
+        AccountMeta::new_readonly(new_authority.pubkey(), false),
+    ]);
+
+    let instructions = [
+        vault_instruction(
+            program_id,
+            &owner.pubkey(),
+            VaultInstruction::Deposit {
+                vault_name: vec![],
+                amount: 1_000_000,
+            },
+        ),
+        set_guardians,
+    ];
+    send(&mut context, &instructions, &[&owner]).await.unwrap();
+
+    let approve = |guardian: &Keypair, new_authority: &Pubkey| {
+        let mut instruction = vault_instruction(
+            program_id,
+            &owner.pubkey(),
+            VaultInstruction::ApproveRecovery { vault_name: vec![] },
+        );
+        instruction.accounts[0].is_signer = false;
+        instruction.accounts.extend([
+            AccountMeta::new(guardians_address, false),
+            AccountMeta::new_readonly(guardian.pubkey(), true),
+            AccountMeta::new_readonly(*new_authority, false),
+        ]);
+        instruction
+    };
+    let mut execute = vault_instruction(
+        program_id,
+        &owner.pubkey(),
+        VaultInstruction::ExecuteRecovery { vault_name: vec![] },
+    );
+    execute.accounts[0].is_signer = false;
+    execute.accounts.extend([
+        AccountMeta::new(guardians_address, false),
+        AccountMeta::new_readonly(new_authority.pubkey(), true),
+    ]);
+    let mut cancel = vault_instruction(
+        program_id,
+        &owner.pubkey(),
+        VaultInstruction::CancelRecovery { vault_name: vec![] },
+    );
+    cancel
+        .accounts
+        .push(AccountMeta::new(guardians_address, false));
+
+    let result = send(
+        &mut context,
+        &[approve(&new_authority, &new_authority.pubkey())],
+        &[&new_authority],
+    )
+    .await;
+    assert_eq!(custom_error(result), Some(VaultError::NotGuardian));
+
+    send(
+        &mut context,
+        &[
+            approve(&guardians[0], &new_authority.pubkey()),
+            approve(&guardians[1], &new_authority.pubkey()),
+        ],
+        &[&guardians[0], &guardians[1]],
+    )
+    .await
+    .unwrap();
+
+    let result = send(&mut context, &[execute.clone()], &[&new_authority]).await;
+    assert_eq!(custom_error(result), Some(VaultError::RecoveryNotReady));
+
+    send(&mut context, &[cancel], &[&owner]).await.unwrap();
+
+    send(
+        &mut context,
+        &[
+            approve(&guardians[1], &new_authority.pubkey()),
+            approve(&guardians[2], &new_authority.pubkey()),
+        ],
+        &[&guardians[1], &guardians[2]],
+    )
+    .await
+    .unwrap();
+
+    let result = send(
+        &mut context,
+        &[approve(&guardians[0], &Pubkey::default())],
+        &[&guardians[0]],
+    )
+    .await;
+    assert_eq!(
+        custom_error(result),
+        Some(VaultError::InvalidRecoveryAuthority),
+    );
+
+    // A dissenting guardian can't hold up the recovery the others back.
+    send(
+        &mut context,
+        &[approve(&guardians[0], &Pubkey::new_unique())],
+        &[&guardians[0]],
+    )
+    .await
+    .unwrap();
+
+    clock.unix_timestamp = start + state::RECOVERY_TIMELOCK;
+    context.set_sysvar(&clock);
+
+    send(&mut context, &[execute], &[&new_authority])
+        .await
+        .unwrap();
+
+    let withdraw = vault_instruction(
+        program_id,
+        &owner.pubkey(),
+        VaultInstruction::Withdraw {
+            vault_name: vec![],
+            amount: 1_000,
+        },
+    );
+    let result = send(&mut context, &[withdraw], &[&owner]).await;
+    assert_eq!(custom_error(result), Some(VaultError::InvalidVaultOwner));
+
+    let mut withdraw = vault_instruction(
+        program_id,
+        &new_authority.pubkey(),
+        VaultInstruction::Withdraw {
+            vault_name: vec![],
+            amount: 250_000,
+        },
+    );
+    withdraw.accounts[1].pubkey = vault;
+    send(&mut context, &[withdraw], &[&new_authority])
+        .await
+        .unwrap();
+
+    let recovered = state::Vault::unpack(&vault_account(&mut context, vault).await.data).unwrap();
+
+    assert_eq!(recovered.owner, owner.pubkey());
+    assert_eq!(recovered.authority(), new_authority.pubkey());
+    assert_eq!(recovered.balance, 750_000);
+    assert_eq!(
+        vault_account(&mut context, new_authority.pubkey())
+            .await
+            .lamports,
+        1_000_000_000 + 250_000,
+    );
+}
+
+fn config_instruction(
+    program_id: Pubkey,
+    signer: &Pubkey,
+    instruction: VaultInstruction,
+    accounts: &[Pubkey],
+) -> Instruction {
+    let mut metas = vec![
+        AccountMeta::new(*signer, true),
+        AccountMeta::new(state::Config::find_address(&program_id).0, false),
+        AccountMeta::new_readonly(solana_program::system_program::ID, false),
+    ];
+    metas.extend(
+        accounts
+            .iter()
+            .map(|account| AccountMeta::new_readonly(*account, false)),
+    );
+
+    Instruction::new_with_borsh(program_id, &instruction, metas)
+}
+
+/// Adds the `ProgramData` account the upgradeable loader would hold for
+/// `program_id` and returns its address.
+fn add_program_data(
+    program_test: &mut ProgramTest,
+    program_id: &Pubkey,
+    upgrade_authority: &Pubkey,
+) -> Pubkey {
+    let program_data = Pubkey::find_program_address(
+        &[program_id.as_ref()],
+        &solana_program::bpf_loader_upgradeable::id(),
+    )
+    .0;
+
+    program_test.add_account(
+        program_data,
+        Account::new_data(
+            1_000_000_000,
+            &solana_program::bpf_loader_upgradeable::UpgradeableLoaderState::ProgramData {
+                slot: 0,
+                upgrade_authority_address: Some(*upgrade_authority),
+            },
+            &solana_program::bpf_loader_upgradeable::id(),
+        )
+        .unwrap(),
+    );
+
+    program_data
+}
+
+#[tokio::test]
+async fn test_config_pause() {
+    let program_id = Pubkey::new_unique();
+    let upgrade_authority = Keypair::new();
+    let admin = Keypair::new();
+    let user = Keypair::new();
+    let treasury = Pubkey::new_unique();
+
+    let mut program_test = program_test(program_id);
+    for account in [&upgrade_authority, &admin, &user] {
+        program_test.add_account(
+            account.pubkey(),
+            Account::new(1_000_000_000, 0, &solana_program::system_program::ID),
+        );
+    }
+    let program_data =
+        add_program_data(&mut program_test, &program_id, &upgrade_authority.pubkey());
+
+    let mut context = program_test.start_with_context().await;
+
+    let deposit = vault_instruction(
+        program_id,
+        &user.pubkey(),
+        VaultInstruction::Deposit {
+            vault_name: vec![],
+            amount: 1_000_000,
+        },
+    );
+    send(&mut context, std::slice::from_ref(&deposit), &[&user])
+        .await
+        .unwrap();
+
+    let initialize = |signer: &Pubkey| {
+        config_instruction(
+            program_id,
+            signer,
+            VaultInstruction::InitializeConfig {
+                deposit_fee_bps: 0,
+                withdraw_fee_bps: 0,
+            },
+            &[program_data, admin.pubkey(), treasury],
+        )
+    };
+
+    let result = send(&mut context, &[initialize(&admin.pubkey())], &[&admin]).await;
+    assert_eq!(
+        custom_error(result),
+        Some(VaultError::InvalidUpgradeAuthority)
+    );
+
+    send(
+        &mut context,
+        &[initialize(&upgrade_authority.pubkey())],
+        &[&upgrade_authority],
+    )
+    .await
+    .unwrap();
+
+    let pause = |signer: &Pubkey, paused: bool| {
+        config_instruction(
+            program_id,
+            signer,
+            VaultInstruction::SetPaused { paused },
+            &[],
+        )
+    };
+
+    let result = send(&mut context, &[pause(&user.pubkey(), true)], &[&user]).await;
+    assert_eq!(custom_error(result), Some(VaultError::InvalidConfigAdmin));
+
+    send(&mut context, &[pause(&admin.pubkey(), true)], &[&admin])
+        .await
+        .unwrap();
+
+    let result = send(&mut context, std::slice::from_ref(&deposit), &[&user]).await;
+    assert_eq!(custom_error(result), Some(VaultError::ProgramPaused));
+
+    send(
+        &mut context,
+        &[vault_instruction(
+            program_id,
+            &user.pubkey(),
+            VaultInstruction::Balance { vault_name: vec![] },
+        )],
+        &[&user],
+    )
+    .await
+    .unwrap();
+
+    send(&mut context, &[pause(&admin.pubkey(), false)], &[&admin])
+        .await
+        .unwrap();
+    send(&mut context, std::slice::from_ref(&deposit), &[&user])
+        .await
+        .unwrap();
+
+    let mut spoofed_config = deposit;
+    spoofed_config.accounts[3].pubkey = Pubkey::new_unique();
+    let result = send(&mut context, &[spoofed_config], &[&user]).await;
+    assert_eq!(custom_error(result), Some(VaultError::InvalidConfigAddress));
+
+    let config = state::Config::unpack(
+        &vault_account(&mut context, state::Config::find_address(&program_id).0)
+            .await
+            .data,
+    )
+    .unwrap();
+
+    assert_eq!(config.admin, admin.pubkey());
+    assert_eq!(config.treasury, treasury);
+    assert!(!config.paused);
+
+    // The new admin and treasury come from the instruction data, not from
+    // whatever accounts trail it.
+    let new_admin = Pubkey::new_unique();
+    let new_treasury = Pubkey::new_unique();
+    let update = config_instruction(
+        program_id,
+        &admin.pubkey(),
+        VaultInstruction::UpdateConfig {
+            new_admin,
+            treasury: new_treasury,
+            deposit_fee_bps: 10,
+            withdraw_fee_bps: 20,
+        },
+        &[user.pubkey(), user.pubkey()],
+    );
+    send(&mut context, &[update], &[&admin]).await.unwrap();
+
+    let config = state::Config::unpack(
+        &vault_account(&mut context, state::Config::find_address(&program_id).0)
+            .await
+            .data,
+    )
+    .unwrap();
+
+    assert_eq!(config.admin, new_admin);
+    assert_eq!(config.treasury, new_treasury);
+    assert_eq!(config.deposit_fee_bps, 10);
+    assert_eq!(config.withdraw_fee_bps, 20);
+
+    let result = send(&mut context, &[pause(&admin.pubkey(), true)], &[&admin]).await;
+    assert_eq!(custom_error(result), Some(VaultError::InvalidConfigAdmin));
+}
+
+#[tokio::test]
+async fn test_protocol_fees() {
+    let program_id = Pubkey::new_unique();
+    let admin = Keypair::new();
+    let user = Keypair::new();
+    let treasury = Pubkey::new_unique();
+    let vault = vault_address(&program_id, &user.pubkey());
+
+    let mut program_test = program_test(program_id);
+    for account in [admin.pubkey(), user.pubkey(), treasury] {
+        program_test.add_account(
+            account,
+            Account::new(1_000_000_000, 0, &solana_program::system_program::ID),
+        );
+    }
+    let program_data = add_program_data(&mut program_test, &program_id, &admin.pubkey());
+
+    let mut context = program_test.start_with_context().await;
+
+    let initialize = config_instruction(
+        program_id,
+        &admin.pubkey(),
+        VaultInstruction::InitializeConfig {
+            deposit_fee_bps: 100,
+            withdraw_fee_bps: 50,
+        },
+        &[program_data, admin.pubkey(), treasury],
+    );
+    send(&mut context, &[initialize], &[&admin]).await.unwrap();
+
+    let with_treasury = |mut instruction: Instruction| {
+        instruction.accounts.push(AccountMeta::new(treasury, false));
+        instruction
+    };
+
+    let deposit = vault_instruction(
+        program_id,
+        &user.pubkey(),
+        VaultInstruction::Deposit {
+            vault_name: vec![],
+            amount: 1_000_000,
+        },
+    );
+    let result = send(&mut context, std::slice::from_ref(&deposit), &[&user]).await;
+    assert_eq!(
+        custom_error(result),
+        Some(VaultError::InvalidTreasuryAddress)
+    );
+
+    send(&mut context, &[with_treasury(deposit.clone())], &[&user])
+        .await
+        .unwrap();
+
+    let withdraw = vault_instruction(
+        program_id,
+        &user.pubkey(),
+        VaultInstruction::Withdraw {
+            vault_name: vec![],
+            amount: 200_000,
+        },
+    );
+    send(&mut context, &[with_treasury(withdraw)], &[&user])
+        .await
+        .unwrap();
+
+    let account = vault_account(&mut context, vault).await;
+    let stored = state::Vault::unpack(&account.data).unwrap();
+
+    assert_eq!(stored.balance, 1_000_000 - 10_000 - 200_000);
+    assert_eq!(stored.total_withdrawn, 200_000);
+    assert_eq!(
+        account.lamports,
+        Rent::default().minimum_balance(state::Vault::LEN) + stored.balance,
+    );
+    assert_eq!(
+        vault_account(&mut context, treasury).await.lamports,
+        1_000_000_000 + 10_000 + 1_000,
+    );
+
+    let mut exempt = config_instruction(
+        program_id,
+        &admin.pubkey(),
+        VaultInstruction::SetFeeExempt { exempt: true },
+        &[vault],
+    );
+    exempt.accounts[3].is_writable = true;
+    send(&mut context, &[exempt], &[&admin]).await.unwrap();
+
+    send(&mut context, &[deposit], &[&user]).await.unwrap();
+
+    let stored = state::Vault::unpack(&vault_account(&mut context, vault).await.data).unwrap();
+
+    assert!(stored.fee_exempt);
+    assert_eq!(stored.balance, 1_790_000);
+}
+
+#[tokio::test]
+async fn test_withdraw_fee_on_every_withdrawal_path() {
+    let program_id = Pubkey::new_unique();
+    let admin = Keypair::new();
+    let user = Keypair::new();
+    let closer = Keypair::new();
+    let treasury = Pubkey::new_unique();
+    let recipient = Pubkey::new_unique();
+    let vault = vault_address(&program_id, &user.pubkey());
+
+    let mut program_test = program_test(program_id);
+    for account in [
+        admin.pubkey(),
+        user.pubkey(),
+        closer.pubkey(),
+        treasury,
+        recipient,
+    ] {
+        program_test.add_account(
+            account,
+            Account::new(1_000_000_000, 0, &solana_program::system_program::ID),
+        );
+    }
+    let program_data = add_program_data(&mut program_test, &program_id, &admin.pubkey());
+
+    let mut context = program_test.start_with_context().await;
+
+    let clock: Clock = context.banks_client.get_sysvar().await.unwrap();
+
+    let initialize = config_instruction(
+        program_id,
+        &admin.pubkey(),
+        VaultInstruction::InitializeConfig {
+            deposit_fee_bps: 0,
+            withdraw_fee_bps: 100,
+        },
+        &[program_data, admin.pubkey(), treasury],
+    );
+    let deposit = vault_instruction(
+        program_id,
+        &user.pubkey(),
+        VaultInstruction::Deposit {
+            vault_name: vec![],
+            amount: 2_000_000,
+        },
+    );
+    send(&mut context, &[initialize, deposit], &[&admin, &user])
+        .await
+        .unwrap();
+
+    let with_treasury = |mut instruction: Instruction| {
+        instruction.accounts.push(AccountMeta::new(treasury, false));
+        instruction
+    };
+
+    let mut withdraw_to = vault_instruction(
+        program_id,
+        &user.pubkey(),
+        VaultInstruction::WithdrawTo {
+            vault_name: vec![],
+            amount: 100_000,
+        },
+    );
+    withdraw_to
+        .accounts
+        .push(AccountMeta::new(recipient, false));
+    let result = send(&mut context, std::slice::from_ref(&withdraw_to), &[&user]).await;
+    assert_eq!(
+        custom_error(result),
+        Some(VaultError::InvalidTreasuryAddress)
+    );
+
+    let open_stream = stream_instruction(
+        program_id,
+        &user.pubkey(),
+        &recipient,
+        VaultInstruction::OpenStream {
+            vault_name: vec![],
+            amount: 200_000,
+            stream_id: 1,
+            rate: 1_000,
+        },
+    );
+    send(
+        &mut context,
+        &[with_treasury(withdraw_to), with_treasury(open_stream)],
+        &[&user],
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(
+        vault_account(&mut context, recipient).await.lamports,
+        1_000_000_000 + 99_000
+    );
+
+    let stream = state::Stream::find_address(&program_id, &user.pubkey(), &recipient, 1).0;
+    let stream = state::Stream::unpack(&vault_account(&mut context, stream).await.data).unwrap();
+
+    assert_eq!(stream.deposited, 198_000);
+
+    let mut enable = vault_instruction(
+        program_id,
+        &user.pubkey(),
+        VaultInstruction::EnableMultisig {
+            vault_name: vec![],
+            threshold: 1,
+            owners: vec![user.pubkey()],
+        },
+    );
+    enable.accounts.push(AccountMeta::new(
+        state::Multisig::find_address(&program_id, &vault).0,
+        false,
+    ));
+    let propose = proposal_instruction(
+        program_id,
+        &user.pubkey(),
+        &user.pubkey(),
+        VaultInstruction::Propose {
+            vault_name: vec![],
+            amount: 300_000,
+            expires_at: clock.unix_timestamp + 3_600,
+        },
+        0,
+        Some(&recipient),
+    );
+    let execute = proposal_instruction(
+        program_id,
+        &user.pubkey(),
+        &user.pubkey(),
+        VaultInstruction::Execute {
+            vault_name: vec![],
+            proposal_id: 0,
+        },
+        0,
+        Some(&recipient),
+    );
+    send(
+        &mut context,
+        &[enable, propose, with_treasury(execute)],
+        &[&user],
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(
+        vault_account(&mut context, recipient).await.lamports,
+        1_000_000_000 + 99_000 + 297_000
+    );
+    assert_eq!(
+        vault_account(&mut context, treasury).await.lamports,
+        1_000_000_000 + 1_000 + 2_000 + 3_000,
+    );
+
+    let stored = state::Vault::unpack(&vault_account(&mut context, vault).await.data).unwrap();
+
+    assert_eq!(stored.balance, 2_000_000 - 100_000 - 200_000 - 300_000);
+
+    let deposit = vault_instruction(
+        program_id,
+        &closer.pubkey(),
+        VaultInstruction::Deposit {
+            vault_name: vec![],
+            amount: 400_000,
+        },
+    );
+    let mut close = vault_instruction(
+        program_id,
+        &closer.pubkey(),
+        VaultInstruction::Close { vault_name: vec![] },
+    );
+    close.accounts.push(AccountMeta::new(recipient, false));
+    send(&mut context, &[deposit, with_treasury(close)], &[&closer])
+        .await
+        .unwrap();
+
+    // Closing withdraws the whole balance; the rent reserve goes back fee-free.
+    assert_eq!(
+        vault_account(&mut context, recipient).await.lamports,
+        1_000_000_000
+            + 99_000
+            + 297_000
+            + 396_000
+            + Rent::default().minimum_balance(state::Vault::LEN),
+    );
+    assert_eq!(
+        vault_account(&mut context, treasury).await.lamports,
+        1_000_000_000 + 1_000 + 2_000 + 3_000 + 4_000,
+    );
+}
+
+#[tokio::test]
+async fn test_balance_return_data() {
+    let program_id = Pubkey::new_unique();
+    let user = Keypair::new();
+
+    let mut program_test = program_test(program_id);
+    program_test.add_account(
+        user.pubkey(),
+        Account::new(1_000_000_000, 0, &solana_program::system_program::ID),
+    );
+
+    let mut context = program_test.start_with_context().await;
+
+    let balance = vault_instruction(
+        program_id,
+        &user.pubkey(),
+        VaultInstruction::Balance { vault_name: vec![] },
+    );
+
+    // Reading a vault never creates it.
+    let result = send(&mut context, std::slice::from_ref(&balance), &[&user]).await;
+    assert_eq!(
+        custom_error(result),
+        Some(VaultError::InvalidAccountDiscriminator)
+    );
+    assert!(context
+        .banks_client
+        .get_account(vault_address(&program_id, &user.pubkey()))
+        .await
+        .unwrap()
+        .is_none());
+
+    let deposit = vault_instruction(
+        program_id,
+        &user.pubkey(),
+        VaultInstruction::Deposit {
+            vault_name: vec![],
+            amount: 700_000,
+        },
+    );
+    send(&mut context, &[deposit], &[&user]).await.unwrap();
+
+    let blockhash = context.get_new_latest_blockhash().await.unwrap();
+    let transaction =
+        Transaction::new_signed_with_payer(&[balance], Some(&user.pubkey()), &[&user], blockhash);
+
+    let simulation = context
+        .banks_client
+        .simulate_transaction(transaction)
+        .await
+        .unwrap();
+    simulation.result.unwrap().unwrap();
+
+    let return_data = simulation.simulation_details.unwrap().return_data.unwrap();
+    let vault = state::Vault::unpack(&return_data.data).unwrap();
+
+    assert_eq!(return_data.program_id, program_id);
+    assert_eq!(vault.owner, user.pubkey());
+    assert_eq!(vault.balance, 700_000);
+    assert_eq!(vault.total_deposited, 700_000);
+}
+
+#[tokio::test]
+async fn test_spoofed_accounts_rejected() {
+    let program_id = Pubkey::new_unique();
+    let user = Keypair::new();
+    let fake_program = Pubkey::new_unique();
+    let mint = Pubkey::new_unique();
+
+    let mut program_test = program_test(program_id);
+    program_test.add_account(
+        user.pubkey(),
+        Account::new(1_000_000_000, 0, &solana_program::system_program::ID),
+    );
+    program_test.add_account(mint, Account::new(1_000_000_000, 0, &spl_token::id()));
+
+    let mut context = program_test.start_with_context().await;
+
+    let mut fake_system_program = vault_instruction(
+        program_id,
+        &user.pubkey(),
+        VaultInstruction::Deposit {
+            vault_name: vec![],
+            amount: 50_000,
+        },
+    );
+    fake_system_program.accounts[2].pubkey = fake_program;
+
+    assert_eq!(
+        custom_error(send(&mut context, &[fake_system_program], &[&user]).await),
+        Some(VaultError::InvalidProgramAccount)
+    );
+
+    let fake_token_program = token_instruction(
+        program_id,
+        &user.pubkey(),
+        &mint,
+        &fake_program,
+        VaultInstruction::DepositToken {
+            vault_name: vec![],
+            amount: 1_000,
+        },
+    );
+
+    assert_eq!(
+        custom_error(send(&mut context, &[fake_token_program], &[&user]).await),
+        Some(VaultError::InvalidProgramAccount)
+    );
+
+    let mut fake_associated_token_program = token_instruction(
+        program_id,
+        &user.pubkey(),
+        &mint,
+        &spl_token::id(),
+        VaultInstruction::DepositToken {
+            vault_name: vec![],
+            amount: 1_000,
+        },
+    );
+    fake_associated_token_program.accounts[9].pubkey = fake_program;
+
+    assert_eq!(
+        custom_error(send(&mut context, &[fake_associated_token_program], &[&user]).await),
+        Some(VaultError::InvalidProgramAccount)
+    );
+
+    let mut fake_vault_token_account = token_instruction(
+        program_id,
+        &user.pubkey(),
+        &mint,
+        &spl_token::id(),
+        VaultInstruction::DepositToken {
+            vault_name: vec![],
+            amount: 1_000,
+        },
+    );
+    fake_vault_token_account.accounts[6].pubkey = Pubkey::new_unique();
+
+    assert_eq!(
+        custom_error(send(&mut context, &[fake_vault_token_account], &[&user]).await),
+        Some(VaultError::InvalidTokenAccount)
+    );
+}
+
+#[tokio::test]
+async fn test_foreign_owned_accounts_rejected() {
+    let program_id = Pubkey::new_unique();
+    let other_program = Pubkey::new_unique();
+    let user = Keypair::new();
+    let spoofer = Keypair::new();
+    let recipient = Keypair::new();
+    let foreign_account = Account {
+        lamports: 1_000_000_000,
+        data: vec![0; state::Vault::LEN],
+        owner: other_program,
+        ..Account::default()
+    };
+
+    let mut program_test = program_test(program_id);
+    for account in [&user, &spoofer, &recipient] {
+        program_test.add_account(
+            account.pubkey(),
+            Account::new(1_000_000_000, 0, &solana_program::system_program::ID),
+        );
+    }
+    program_test.add_account(
+        vault_address(&program_id, &spoofer.pubkey()),
+        foreign_account.clone(),
+    );
+    program_test.add_account(
+        state::Stream::find_address(&program_id, &user.pubkey(), &recipient.pubkey(), 1).0,
+        foreign_account.clone(),
+    );
+
+    let mut context = program_test.start_with_context().await;
+
+    assert_eq!(
+        custom_error(
+            send(
+                &mut context,
+                &[vault_instruction(
+                    program_id,
+                    &spoofer.pubkey(),
+                    VaultInstruction::Deposit {
+                        vault_name: vec![],
+                        amount: 50_000
+                    }
+                )],
+                &[&spoofer],
+            )
+            .await
+        ),
+        Some(VaultError::InvalidAccountOwner)
+    );
+
+    let mut claim = stream_instruction(
+        program_id,
+        &user.pubkey(),
+        &recipient.pubkey(),
+        VaultInstruction::ClaimStream {
+            vault_name: vec![],
+            stream_id: 1,
+        },
+    );
+    claim.accounts[0].is_signer = false;
+    claim.accounts[4].is_signer = true;
+
+    let instructions = [
+        vault_instruction(
+            program_id,
+            &user.pubkey(),
+            VaultInstruction::Deposit {
+                vault_name: vec![],
+                amount: 50_000,
+            },
+        ),
+        claim,
+    ];
+
+    assert_eq!(
+        custom_error(send(&mut context, &instructions, &[&user, &recipient]).await),
+        Some(VaultError::InvalidAccountOwner)
+    );
+}
+
+#[tokio::test]
+async fn test_foreign_owned_companion_accounts_rejected() {
+    let program_id = Pubkey::new_unique();
+    let other_program = Pubkey::new_unique();
+    let user = Keypair::new();
+    let multisig_creator = Keypair::new();
+    let delegate = Keypair::new();
+    let vault = vault_address(&program_id, &user.pubkey());
+    let multisig_vault = vault_address(&program_id, &multisig_creator.pubkey());
+    let foreign_account = Account {
+        lamports: 1_000_000_000,
+        data: vec![0; state::Multisig::LEN],
+        owner: other_program,
+        ..Account::default()
+    };
+
+    let mut program_test = program_test(program_id);
+    for account in [&user, &multisig_creator, &delegate] {
+        program_test.add_account(
+            account.pubkey(),
+            Account::new(1_000_000_000, 0, &solana_program::system_program::ID),
+        );
+    }
+    for address in [
+        state::Multisig::find_address(&program_id, &vault).0,
+        state::Proposal::find_address(&program_id, &multisig_vault, 0).0,
+        state::Allowance::find_address(&program_id, &vault, &delegate.pubkey()).0,
+    ] {
+        program_test.add_account(address, foreign_account.clone());
+    }
+
+    let mut context = program_test.start_with_context().await;
+
+    for creator in [&user, &multisig_creator] {
+        send(
+            &mut context,
+            &[vault_instruction(
+                program_id,
+                &creator.pubkey(),
+                VaultInstruction::Deposit {
+                    vault_name: vec![],
+                    amount: 1_000_000,
+                },
+            )],
+            &[creator],
+        )
+        .await
+        .unwrap();
+    }
+
+    let propose = proposal_instruction(
+        program_id,
+        &user.pubkey(),
+        &user.pubkey(),
+        VaultInstruction::Propose {
+            vault_name: vec![],
+            amount: 100_000,
+            expires_at: i64::MAX,
+        },
+        0,
+        Some(&delegate.pubkey()),
+    );
+
+    assert_eq!(
+        custom_error(send(&mut context, &[propose], &[&user]).await),
+        Some(VaultError::InvalidAccountOwner)
+    );
+
+    let mut enable = vault_instruction(
+        program_id,
+        &multisig_creator.pubkey(),
+        VaultInstruction::EnableMultisig {
+            vault_name: vec![],
+            threshold: 1,
+            owners: vec![multisig_creator.pubkey()],
+        },
+    );
+    enable.accounts.push(AccountMeta::new(
+        state::Multisig::find_address(&program_id, &multisig_vault).0,
+        false,
+    ));
+    send(&mut context, &[enable], &[&multisig_creator])
+        .await
+        .unwrap();
+
+    let approve = proposal_instruction(
+        program_id,
+        &multisig_creator.pubkey(),
+        &multisig_creator.pubkey(),
+        VaultInstruction::Approve {
+            vault_name: vec![],
+            proposal_id: 0,
+        },
+        0,
+        None,
+    );
+
+    assert_eq!(
+        custom_error(send(&mut context, &[approve], &[&multisig_creator]).await),
+        Some(VaultError::InvalidAccountOwner)
+    );
+
+    let delegate_withdraw =
+        delegate_withdraw_instruction(program_id, &user.pubkey(), &delegate.pubkey(), 100_000);
+
+    assert_eq!(
+        custom_error(send(&mut context, &[delegate_withdraw], &[&delegate]).await),
+        Some(VaultError::InvalidAccountOwner)
+    );
+}
+
+#[tokio::test]
+async fn test_foreign_owned_config_rejected() {
+    let program_id = Pubkey::new_unique();
+    let user = Keypair::new();
+
+    let mut program_test = program_test(program_id);
+    program_test.add_account(
+        user.pubkey(),
+        Account::new(1_000_000_000, 0, &solana_program::system_program::ID),
+    );
+    program_test.add_account(
+        state::Config::find_address(&program_id).0,
+        Account {
+            lamports: 1_000_000_000,
+            data: vec![0; state::Config::LEN],
+            owner: Pubkey::new_unique(),
+            ..Account::default()
+        },
+    );
+
+    let mut context = program_test.start_with_context().await;
+
+    assert_eq!(
+        custom_error(
+            send(
+                &mut context,
+                &[vault_instruction(
+                    program_id,
+                    &user.pubkey(),
+                    VaultInstruction::Deposit {
+                        vault_name: vec![],
+                        amount: 50_000
+                    }
+                )],
+                &[&user],
+            )
+            .await
+        ),
+        Some(VaultError::InvalidAccountOwner)
+    );
+}
+
+#[tokio::test]
+async fn test_readonly_and_unsigned_accounts_rejected() {
+    let program_id = Pubkey::new_unique();
+    let user = Keypair::new();
+    let grantor = Keypair::new();
+    let recipient = Pubkey::new_unique();
+
+    let mut program_test = program_test(program_id);
+    for account in [&user, &grantor] {
+        program_test.add_account(
+            account.pubkey(),
+            Account::new(1_000_000_000, 0, &solana_program::system_program::ID),
+        );
+    }
+    program_test.add_account(
+        recipient,
+        Account::new(5_000_000, 0, &solana_program::system_program::ID),
+    );
+
+    let mut context = program_test.start_with_context().await;
+
+    let mut readonly_vault = vault_instruction(
+        program_id,
+        &user.pubkey(),
+        VaultInstruction::Deposit {
+            vault_name: vec![],
+            amount: 50_000,
+        },
+    );
+    readonly_vault.accounts[1].is_writable = false;
+
+    assert_eq!(
+        custom_error(send(&mut context, &[readonly_vault], &[&user]).await),
+        Some(VaultError::AccountNotWritable)
+    );
+
+    let mut readonly_recipient = vault_instruction(
+        program_id,
+        &user.pubkey(),
+        VaultInstruction::WithdrawTo {
+            vault_name: vec![],
+            amount: 30_000,
+        },
+    );
+    readonly_recipient
+        .accounts
+        .push(AccountMeta::new_readonly(recipient, false));
+
+    let instructions = [
+        vault_instruction(
+            program_id,
+            &user.pubkey(),
+            VaultInstruction::Deposit {
+                vault_name: vec![],
+                amount: 50_000,
+            },
+        ),
+        readonly_recipient,
+    ];
+
+    assert_eq!(
+        custom_error(send(&mut context, &instructions, &[&user]).await),
+        Some(VaultError::AccountNotWritable)
+    );
+
+    let mut unsigned_grant = grant_instruction(
+        program_id,
+        &user.pubkey(),
+        &grantor.pubkey(),
+        100_000,
+        (0, 0, 1),
+        true,
+    );
+    unsigned_grant.accounts[4].is_signer = false;
+
+    assert_eq!(
+        custom_error(send(&mut context, &[unsigned_grant], &[&user]).await),
+        Some(VaultError::MissingSignature)
+    );
+
+    let mut readonly_grantor = grant_instruction(
+        program_id,
+        &user.pubkey(),
+        &grantor.pubkey(),
+        100_000,
+        (0, 0, 1),
+        true,
+    );
+    readonly_grantor.accounts[4].is_writable = false;
+
+    assert_eq!(
+        custom_error(send(&mut context, &[readonly_grantor], &[&user, &grantor]).await),
+        Some(VaultError::AccountNotWritable)
+    );
+}
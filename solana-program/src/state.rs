@@ -0,0 +1,1923 @@
+use borsh::{BorshDeserialize, BorshSerialize};
+
+/// Marks an account as a vault created by this program.
+pub const VAULT_DISCRIMINATOR: [u8; 8] = *b"pdavault";
+
+/// Current layout version of [`Vault`]. Layouts only ever append fields whose
+/// zeroed bytes are a valid default, so older vaults migrate by zero-extension.
+pub const VAULT_VERSION: u8 = 11;
+
+/// Byte offset of [`Vault::owner`], for `getProgramAccounts` memcmp filters.
+pub const VAULT_OWNER_OFFSET: usize = 9;
+
+/// Longest vault name accepted as an extra PDA seed.
+pub const MAX_VAULT_NAME_LEN: usize = 32;
+
+/// Length of the withdrawal limit window in seconds.
+pub const WITHDRAWAL_LIMIT_WINDOW: i64 = 24 * 60 * 60;
+
+/// Seconds before a looser withdrawal limit takes effect.
+pub const WITHDRAWAL_LIMIT_DELAY: i64 = 24 * 60 * 60;
+
+/// Seconds before a new allowlist destination, or disabling the allowlist,
+/// takes effect.
+pub const ALLOWLIST_COOLDOWN: i64 = 24 * 60 * 60;
+
+/// Seconds between guardians reaching their threshold and the recovery
+/// becoming executable, leaving the authority time to cancel it.
+pub const RECOVERY_TIMELOCK: i64 = 3 * 24 * 60 * 60;
+
+/// Marks an account as a per-mint token balance of a vault.
+pub const TOKEN_BALANCE_DISCRIMINATOR: [u8; 8] = *b"vaulttok";
+
+/// Current layout version of [`TokenBalance`].
+pub const TOKEN_BALANCE_VERSION: u8 = 1;
+
+/// Seed prefix of the per-mint token balance PDA.
+pub const TOKEN_BALANCE_SEED: &[u8] = b"token";
+
+/// Marks an account as a payment stream between two vault owners.
+pub const STREAM_DISCRIMINATOR: [u8; 8] = *b"vaultstm";
+
+/// Current layout version of [`Stream`].
+pub const STREAM_VERSION: u8 = 1;
+
+/// Seed prefix of the payment stream PDA.
+pub const STREAM_SEED: &[u8] = b"stream";
+
+/// Marks an account as the owner set of a multisig vault.
+pub const MULTISIG_DISCRIMINATOR: [u8; 8] = *b"vaultmsg";
+
+/// Current layout version of [`Multisig`].
+pub const MULTISIG_VERSION: u8 = 1;
+
+/// Seed prefix of the multisig PDA.
+pub const MULTISIG_SEED: &[u8] = b"multisig";
+
+/// Largest owner set of a multisig vault.
+pub const MAX_MULTISIG_OWNERS: usize = 10;
+
+/// Marks an account as a multisig withdrawal proposal.
+pub const PROPOSAL_DISCRIMINATOR: [u8; 8] = *b"vaultprp";
+
+/// Current layout version of [`Proposal`].
+pub const PROPOSAL_VERSION: u8 = 1;
+
+/// Seed prefix of the proposal PDA.
+pub const PROPOSAL_SEED: &[u8] = b"proposal";
+
+/// Marks an account as a delegate's spending allowance on a vault.
+pub const ALLOWANCE_DISCRIMINATOR: [u8; 8] = *b"vaultalw";
+
+/// Current layout version of [`Allowance`].
+pub const ALLOWANCE_VERSION: u8 = 2;
+
+/// Seed prefix of the allowance PDA.
+pub const ALLOWANCE_SEED: &[u8] = b"allowance";
+
+/// Marks an account as the destination allowlist of a vault.
+pub const ALLOWLIST_DISCRIMINATOR: [u8; 8] = *b"vaultlst";
+
+/// Current layout version of [`Allowlist`].
+pub const ALLOWLIST_VERSION: u8 = 2;
+
+/// Seed prefix of the allowlist PDA.
+pub const ALLOWLIST_SEED: &[u8] = b"allowlist";
+
+/// Largest number of destinations on an allowlist.
+pub const MAX_ALLOWLIST_ENTRIES: usize = 10;
+
+/// Marks an account as the recovery guardians of a vault.
+pub const GUARDIANS_DISCRIMINATOR: [u8; 8] = *b"vaultgdn";
+
+/// Current layout version of [`Guardians`].
+pub const GUARDIANS_VERSION: u8 = 3;
+
+/// Seed prefix of the guardians PDA.
+pub const GUARDIANS_SEED: &[u8] = b"guardians";
+
+/// Largest guardian set of a vault.
+pub const MAX_GUARDIANS: usize = 10;
+
+/// Marks an account as the program-wide config.
+pub const CONFIG_DISCRIMINATOR: [u8; 8] = *b"vaultcfg";
+
+/// Current layout version of [`Config`].
+pub const CONFIG_VERSION: u8 = 1;
+
+/// Seed of the config singleton PDA.
+pub const CONFIG_SEED: &[u8] = b"config";
+
+/// Fees are expressed in basis points of the moved amount.
+pub const MAX_FEE_BPS: u16 = 10_000;
+
+/// Size of the original layout, which held only a little-endian `u64` balance.
+pub const LEGACY_VAULT_LEN: usize = 8;
+
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub struct Vault {
+    pub discriminator: [u8; 8],
+    pub version: u8,
+    pub owner: solana_program::pubkey::Pubkey,
+    pub bump: u8,
+    pub balance: u64,
+    pub created_at: i64,
+    pub updated_at: i64,
+    pub total_deposited: u64,
+    pub total_withdrawn: u64,
+    /// Length of the vault name, zero for the default vault.
+    pub name_len: u8,
+    pub name: [u8; MAX_VAULT_NAME_LEN],
+    /// Unix timestamp before which nothing can leave the vault, zero if unset.
+    pub unlock_timestamp: i64,
+    /// Slot before which nothing can leave the vault, zero if unset.
+    pub unlock_slot: u64,
+    /// Vesting grant funded by a third party, all zero if there is none.
+    pub vesting: VestingSchedule,
+    /// Whether withdrawals need approved proposals from the [`Multisig`]
+    /// owner set instead of the owner's signature.
+    pub multisig: bool,
+    pub withdrawal_limit: WithdrawalLimit,
+    /// Whether withdrawals may only go to destinations on the [`Allowlist`].
+    pub allowlist_enabled: bool,
+    /// When a requested disabling of the allowlist takes effect, zero if
+    /// none is pending.
+    pub allowlist_disable_at: i64,
+    /// Key that controls the vault after a guardian recovery, the default
+    /// key while `owner` is still in control. The PDA stays derived from
+    /// `owner`.
+    pub authority: solana_program::pubkey::Pubkey,
+    /// Whether deposits and withdrawals skip the protocol fees.
+    pub fee_exempt: bool,
+    /// Slot the vault was created in, zero for vaults migrated from older
+    /// layouts. Allowances, the allowlist and the guardians record it, so
+    /// they don't carry over to a vault created again after a `Close`.
+    pub created_slot: u64,
+    /// Number of mints the vault holds a non-zero token balance of. Vaults
+    /// migrated from older layouts start counting at zero.
+    pub token_balances: u16,
+}
+
+impl Vault {
+    pub const LEN: usize = 8
+        + 1
+        + 32
+        + 1
+        + 8
+        + 8
+        + 8
+        + 8
+        + 8
+        + 1
+        + MAX_VAULT_NAME_LEN
+        + 8
+        + 8
+        + VestingSchedule::LEN
+        + 1
+        + WithdrawalLimit::LEN
+        + 1
+        + 8
+        + 32
+        + 1
+        + 8
+        + 2;
+
+    pub fn new(
+        owner: solana_program::pubkey::Pubkey,
+        bump: u8,
+        vault_name: &[u8],
+        timestamp: i64,
+    ) -> Self {
+        let mut name = [0u8; MAX_VAULT_NAME_LEN];
+        name[..vault_name.len()].copy_from_slice(vault_name);
+
+        Self {
+            discriminator: VAULT_DISCRIMINATOR,
+            version: VAULT_VERSION,
+            owner,
+            bump,
+            balance: 0,
+            created_at: timestamp,
+            updated_at: timestamp,
+            total_deposited: 0,
+            total_withdrawn: 0,
+            name_len: vault_name.len() as u8,
+            name,
+            unlock_timestamp: 0,
+            unlock_slot: 0,
+            vesting: VestingSchedule::default(),
+            multisig: false,
+            withdrawal_limit: WithdrawalLimit::default(),
+            allowlist_enabled: false,
+            allowlist_disable_at: 0,
+            authority: solana_program::pubkey::Pubkey::default(),
+            fee_exempt: false,
+            created_slot: 0,
+            token_balances: 0,
+        }
+    }
+
+    /// Derives the vault PDA. The default vault keeps the original
+    /// `[owner]` seeds, named vaults add the name as a second seed.
+    pub fn find_address(
+        program_id: &solana_program::pubkey::Pubkey,
+        owner: &solana_program::pubkey::Pubkey,
+        vault_name: &[u8],
+    ) -> (solana_program::pubkey::Pubkey, u8) {
+        solana_program::pubkey::Pubkey::find_program_address(
+            &Self::seeds(owner, vault_name),
+            program_id,
+        )
+    }
+
+    pub fn seeds<'a>(
+        owner: &'a solana_program::pubkey::Pubkey,
+        vault_name: &'a [u8],
+    ) -> Vec<&'a [u8]> {
+        match vault_name.is_empty() {
+            true => vec![owner.as_ref()],
+            false => vec![owner.as_ref(), vault_name],
+        }
+    }
+
+    pub fn name(&self) -> &[u8] {
+        &self.name[..self.name_len as usize]
+    }
+
+    /// Rebuilds a vault from the legacy layout. The deposit history of such
+    /// vaults is unknown, so the preserved balance is counted as deposited.
+    pub fn from_legacy(
+        owner: solana_program::pubkey::Pubkey,
+        bump: u8,
+        balance: u64,
+        timestamp: i64,
+    ) -> Self {
+        Self {
+            balance,
+            total_deposited: balance,
+            ..Self::new(owner, bump, &[], timestamp)
+        }
+    }
+
+    /// Returns the layout version of a vault account, `None` for accounts
+    /// without the vault discriminator such as legacy 8-byte vaults.
+    pub fn layout_version(data: &[u8]) -> Option<u8> {
+        match data.len() > 8 && data[..8] == VAULT_DISCRIMINATOR {
+            true => Some(data[8]),
+            false => None,
+        }
+    }
+
+    /// Decodes a vault, rejecting foreign accounts and unknown layouts.
+    pub fn unpack(data: &[u8]) -> Result<Self, solana_program::program_error::ProgramError> {
+        let Some(version) = Self::layout_version(data) else {
+            return Err(crate::error::VaultError::InvalidAccountDiscriminator.into());
+        };
+
+        if version != VAULT_VERSION || data.len() < Self::LEN {
+            return Err(crate::error::VaultError::UnsupportedVaultVersion.into());
+        }
+
+        Self::try_from_slice(&data[..Self::LEN])
+            .map_err(|_| solana_program::program_error::ProgramError::InvalidAccountData)
+    }
+
+    /// Decodes a vault of the current or an older versioned layout, read as
+    /// if it were zero-extended by `Migrate`.
+    pub fn unpack_upgraded(
+        data: &[u8],
+    ) -> Result<Self, solana_program::program_error::ProgramError> {
+        let Some(version) = Self::layout_version(data) else {
+            return Err(crate::error::VaultError::InvalidAccountDiscriminator.into());
+        };
+
+        if version > VAULT_VERSION {
+            return Err(crate::error::VaultError::UnsupportedVaultVersion.into());
+        }
+
+        let mut upgraded = [0u8; Self::LEN];
+        let len = data.len().min(Self::LEN);
+        upgraded[..len].copy_from_slice(&data[..len]);
+        upgraded[8] = VAULT_VERSION;
+
+        Self::unpack(&upgraded)
+    }
+
+    pub fn pack(&self, data: &mut [u8]) -> solana_program::entrypoint::ProgramResult {
+        if data.len() < Self::LEN {
+            return Err(solana_program::program_error::ProgramError::AccountDataTooSmall);
+        }
+
+        self.serialize(&mut &mut data[..Self::LEN])
+            .map_err(|_| solana_program::program_error::ProgramError::InvalidAccountData)
+    }
+
+    pub fn deposit(
+        &mut self,
+        amount: u64,
+        timestamp: i64,
+    ) -> solana_program::entrypoint::ProgramResult {
+        self.balance = self
+            .balance
+            .checked_add(amount)
+            .ok_or(crate::error::VaultError::BalanceOverflow)?;
+        self.total_deposited = self
+            .total_deposited
+            .checked_add(amount)
+            .ok_or(crate::error::VaultError::BalanceOverflow)?;
+        self.updated_at = timestamp;
+
+        Ok(())
+    }
+
+    pub fn withdraw(
+        &mut self,
+        amount: u64,
+        timestamp: i64,
+    ) -> solana_program::entrypoint::ProgramResult {
+        self.balance = self
+            .balance
+            .checked_sub(amount)
+            .ok_or(crate::error::VaultError::InsufficientVaultBalance)?;
+        self.total_withdrawn = self
+            .total_withdrawn
+            .checked_add(amount)
+            .ok_or(crate::error::VaultError::BalanceOverflow)?;
+        self.updated_at = timestamp;
+
+        Ok(())
+    }
+
+    pub fn ensure_unlocked(
+        &self,
+        clock: &solana_program::clock::Clock,
+    ) -> solana_program::entrypoint::ProgramResult {
+        if clock.unix_timestamp < self.unlock_timestamp || clock.slot < self.unlock_slot {
+            solana_program::msg!(
+                "Vault locked until timestamp {} and slot {}",
+                self.unlock_timestamp,
+                self.unlock_slot,
+            );
+
+            return Err(crate::error::VaultError::VaultLocked.into());
+        }
+
+        Ok(())
+    }
+
+    /// Key allowed to act as the vault owner.
+    pub fn authority(&self) -> solana_program::pubkey::Pubkey {
+        match self.authority == solana_program::pubkey::Pubkey::default() {
+            true => self.owner,
+            false => self.authority,
+        }
+    }
+
+    /// Fails for multisig vaults, whose funds only move through proposals.
+    pub fn ensure_single_owner(&self) -> solana_program::entrypoint::ProgramResult {
+        if self.multisig {
+            return Err(crate::error::VaultError::MultisigRequired.into());
+        }
+
+        Ok(())
+    }
+
+    /// Fails unless a companion account recorded this vault's creation slot,
+    /// rather than being left behind by a closed vault at the same address.
+    pub fn ensure_companion(
+        &self,
+        vault_created_slot: u64,
+    ) -> solana_program::entrypoint::ProgramResult {
+        if vault_created_slot != self.created_slot {
+            return Err(crate::error::VaultError::StaleCompanionAccount.into());
+        }
+
+        Ok(())
+    }
+
+    /// Whether the allowlist applies at `timestamp`. Disabling it only takes
+    /// effect once its cooldown has passed.
+    pub fn allowlist_active(&self, timestamp: i64) -> bool {
+        self.allowlist_enabled
+            && (self.allowlist_disable_at == 0 || timestamp < self.allowlist_disable_at)
+    }
+
+    /// Enabling the allowlist applies at once, disabling it after
+    /// [`ALLOWLIST_COOLDOWN`] seconds.
+    pub fn set_allowlist(&mut self, enabled: bool, timestamp: i64) {
+        if enabled {
+            self.allowlist_enabled = true;
+            self.allowlist_disable_at = 0;
+        } else if !self.allowlist_active(timestamp) {
+            self.allowlist_enabled = false;
+            self.allowlist_disable_at = 0;
+        } else if self.allowlist_disable_at == 0 {
+            self.allowlist_disable_at = timestamp + ALLOWLIST_COOLDOWN;
+        }
+
+        self.updated_at = timestamp;
+    }
+
+    /// Moves the unlock point forward. A lock can only be extended, never
+    /// shortened, so it also binds a compromised owner key.
+    pub fn extend_lock(
+        &mut self,
+        lock_kind: crate::instruction::LockKind,
+        unlock_at: u64,
+        timestamp: i64,
+    ) -> solana_program::entrypoint::ProgramResult {
+        match lock_kind {
+            crate::instruction::LockKind::UnixTimestamp => {
+                let unlock_timestamp = i64::try_from(unlock_at)
+                    .map_err(|_| solana_program::program_error::ProgramError::InvalidArgument)?;
+
+                if unlock_timestamp < self.unlock_timestamp {
+                    return Err(crate::error::VaultError::LockShortened.into());
+                }
+
+                self.unlock_timestamp = unlock_timestamp;
+            }
+            crate::instruction::LockKind::Slot => {
+                if unlock_at < self.unlock_slot {
+                    return Err(crate::error::VaultError::LockShortened.into());
+                }
+
+                self.unlock_slot = unlock_at;
+            }
+        }
+
+        self.updated_at = timestamp;
+
+        Ok(())
+    }
+
+    /// Sets the stored balance to the lamports actually held. The drift is
+    /// booked as a deposit or a withdrawal so the totals keep adding up.
+    pub fn sync(
+        &mut self,
+        actual_balance: u64,
+        timestamp: i64,
+    ) -> solana_program::entrypoint::ProgramResult {
+        if actual_balance > self.balance {
+            self.deposit(actual_balance - self.balance, timestamp)
+        } else {
+            self.withdraw(self.balance - actual_balance, timestamp)
+        }
+    }
+}
+
+/// Linear vesting of lamports a grantor deposited into a vault. Nothing
+/// vests before the cliff; from there the grant vests linearly between
+/// `start` and `end`.
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, Default, PartialEq)]
+pub struct VestingSchedule {
+    /// Account that funded the grant, the default key if there is none.
+    pub grantor: solana_program::pubkey::Pubkey,
+    pub start: i64,
+    pub cliff: i64,
+    pub end: i64,
+    pub total: u64,
+    /// Whether the grantor may take back the unvested remainder.
+    pub revocable: bool,
+}
+
+impl VestingSchedule {
+    pub const LEN: usize = 32 + 8 + 8 + 8 + 8 + 1;
+
+    pub fn new(
+        grantor: solana_program::pubkey::Pubkey,
+        terms: &crate::instruction::VestingTerms,
+        total: u64,
+    ) -> Result<Self, solana_program::program_error::ProgramError> {
+        if total == 0 {
+            return Err(crate::error::VaultError::ZeroAmount.into());
+        }
+
+        if terms.start > terms.cliff || terms.cliff > terms.end || terms.start == terms.end {
+            return Err(crate::error::VaultError::InvalidVestingSchedule.into());
+        }
+
+        // The vesting math divides by the duration, which has to fit an i64.
+        terms
+            .end
+            .checked_sub(terms.start)
+            .ok_or(crate::error::VaultError::InvalidVestingSchedule)?;
+
+        Ok(Self {
+            grantor,
+            start: terms.start,
+            cliff: terms.cliff,
+            end: terms.end,
+            total,
+            revocable: terms.revocable,
+        })
+    }
+
+    pub fn vested_amount(&self, timestamp: i64) -> u64 {
+        if timestamp < self.cliff {
+            return 0;
+        }
+
+        if timestamp >= self.end {
+            return self.total;
+        }
+
+        let elapsed = timestamp.abs_diff(self.start) as u128;
+        let duration = self.end.abs_diff(self.start) as u128;
+
+        (self.total as u128 * elapsed / duration) as u64
+    }
+
+    /// Lamports of the grant that must stay in the vault at `timestamp`.
+    pub fn unvested_amount(&self, timestamp: i64) -> u64 {
+        self.total - self.vested_amount(timestamp)
+    }
+}
+
+/// Caps the lamports leaving a vault over a rolling window. Withdrawals use
+/// up the limit, which is released again linearly over
+/// [`WITHDRAWAL_LIMIT_WINDOW`] seconds, so the full limit is never available
+/// twice in a row. All zero means no limit.
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, Default, PartialEq)]
+pub struct WithdrawalLimit {
+    /// Lamports allowed per window, zero for no limit.
+    pub limit: u64,
+    /// When `used` was last released up to.
+    pub used_at: i64,
+    /// Lamports of the limit still in use by earlier withdrawals.
+    pub used: u64,
+    /// Looser limit waiting for its delay to pass.
+    pub pending_limit: u64,
+    /// When `pending_limit` takes effect, zero if nothing is pending.
+    pub pending_at: i64,
+}
+
+impl WithdrawalLimit {
+    pub const LEN: usize = 8 + 8 + 8 + 8 + 8;
+
+    /// Tightening applies at once. Loosening, including removing the limit,
+    /// only applies [`WITHDRAWAL_LIMIT_DELAY`] seconds later, so a stolen
+    /// owner key cannot lift the cap and drain the vault straight away.
+    pub fn set(&mut self, limit: u64, timestamp: i64) {
+        self.apply_pending(timestamp);
+
+        let loosens = self.limit != 0 && (limit == 0 || limit > self.limit);
+
+        if loosens {
+            self.pending_limit = limit;
+            self.pending_at = timestamp + WITHDRAWAL_LIMIT_DELAY;
+        } else {
+            self.limit = limit;
+            self.pending_limit = 0;
+            self.pending_at = 0;
+        }
+    }
+
+    /// Books `amount` against the limit, failing if it does not fit.
+    pub fn consume(
+        &mut self,
+        amount: u64,
+        timestamp: i64,
+    ) -> solana_program::entrypoint::ProgramResult {
+        self.release(timestamp);
+        self.apply_pending(timestamp);
+
+        if self.limit == 0 {
+            self.used = 0;
+            self.used_at = 0;
+
+            return Ok(());
+        }
+
+        let used = self
+            .used
+            .checked_add(amount)
+            .filter(|used| *used <= self.limit)
+            .ok_or(crate::error::VaultError::WithdrawalLimitExceeded)?;
+
+        self.used = used;
+
+        Ok(())
+    }
+
+    /// Releases what `used` drained since `used_at`, at `limit` lamports per
+    /// window. `used_at` only advances by the time the released lamports
+    /// account for, so rounding never swallows a partial release.
+    fn release(&mut self, timestamp: i64) {
+        if self.limit == 0 {
+            return;
+        }
+
+        let elapsed = timestamp.saturating_sub(self.used_at).max(0) as u128;
+        let released = self.limit as u128 * elapsed / WITHDRAWAL_LIMIT_WINDOW as u128;
+
+        if released >= self.used as u128 {
+            self.used = 0;
+            self.used_at = timestamp;
+        } else {
+            self.used -= released as u64;
+            self.used_at +=
+                (released * WITHDRAWAL_LIMIT_WINDOW as u128 / self.limit as u128) as i64;
+        }
+    }
+
+    fn apply_pending(&mut self, timestamp: i64) {
+        if self.pending_at != 0 && timestamp >= self.pending_at {
+            self.limit = self.pending_limit;
+            self.pending_limit = 0;
+            self.pending_at = 0;
+        }
+    }
+}
+
+/// Tracks how many tokens of one mint a vault holds in its associated token
+/// account. Lives in a PDA derived from the vault and the mint.
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub struct TokenBalance {
+    pub discriminator: [u8; 8],
+    pub version: u8,
+    pub vault: solana_program::pubkey::Pubkey,
+    pub mint: solana_program::pubkey::Pubkey,
+    pub bump: u8,
+    pub balance: u64,
+    pub created_at: i64,
+    pub updated_at: i64,
+    pub total_deposited: u64,
+    pub total_withdrawn: u64,
+}
+
+impl TokenBalance {
+    pub const LEN: usize = 8 + 1 + 32 + 32 + 1 + 8 + 8 + 8 + 8 + 8;
+
+    pub fn new(
+        vault: solana_program::pubkey::Pubkey,
+        mint: solana_program::pubkey::Pubkey,
+        bump: u8,
+        timestamp: i64,
+    ) -> Self {
+        Self {
+            discriminator: TOKEN_BALANCE_DISCRIMINATOR,
+            version: TOKEN_BALANCE_VERSION,
+            vault,
+            mint,
+            bump,
+            balance: 0,
+            created_at: timestamp,
+            updated_at: timestamp,
+            total_deposited: 0,
+            total_withdrawn: 0,
+        }
+    }
+
+    pub fn find_address(
+        program_id: &solana_program::pubkey::Pubkey,
+        vault: &solana_program::pubkey::Pubkey,
+        mint: &solana_program::pubkey::Pubkey,
+    ) -> (solana_program::pubkey::Pubkey, u8) {
+        solana_program::pubkey::Pubkey::find_program_address(
+            &[TOKEN_BALANCE_SEED, vault.as_ref(), mint.as_ref()],
+            program_id,
+        )
+    }
+
+    pub fn unpack(data: &[u8]) -> Result<Self, solana_program::program_error::ProgramError> {
+        if data.len() < Self::LEN || data[..8] != TOKEN_BALANCE_DISCRIMINATOR {
+            return Err(crate::error::VaultError::InvalidAccountDiscriminator.into());
+        }
+
+        if data[8] != TOKEN_BALANCE_VERSION {
+            return Err(crate::error::VaultError::UnsupportedVaultVersion.into());
+        }
+
+        Self::try_from_slice(&data[..Self::LEN])
+            .map_err(|_| solana_program::program_error::ProgramError::InvalidAccountData)
+    }
+
+    pub fn pack(&self, data: &mut [u8]) -> solana_program::entrypoint::ProgramResult {
+        if data.len() < Self::LEN {
+            return Err(solana_program::program_error::ProgramError::AccountDataTooSmall);
+        }
+
+        self.serialize(&mut &mut data[..Self::LEN])
+            .map_err(|_| solana_program::program_error::ProgramError::InvalidAccountData)
+    }
+
+    pub fn deposit(
+        &mut self,
+        amount: u64,
+        timestamp: i64,
+    ) -> solana_program::entrypoint::ProgramResult {
+        self.balance = self
+            .balance
+            .checked_add(amount)
+            .ok_or(crate::error::VaultError::BalanceOverflow)?;
+        self.total_deposited = self
+            .total_deposited
+            .checked_add(amount)
+            .ok_or(crate::error::VaultError::BalanceOverflow)?;
+        self.updated_at = timestamp;
+
+        Ok(())
+    }
+
+    pub fn withdraw(
+        &mut self,
+        amount: u64,
+        timestamp: i64,
+    ) -> solana_program::entrypoint::ProgramResult {
+        self.balance = self
+            .balance
+            .checked_sub(amount)
+            .ok_or(crate::error::VaultError::InsufficientVaultBalance)?;
+        self.total_withdrawn = self
+            .total_withdrawn
+            .checked_add(amount)
+            .ok_or(crate::error::VaultError::BalanceOverflow)?;
+        self.updated_at = timestamp;
+
+        Ok(())
+    }
+}
+
+/// Lamports escrowed out of a sender's vault and released to a recipient at
+/// a fixed rate. Lives in a PDA derived from sender, recipient and a stream
+/// id chosen by the sender.
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub struct Stream {
+    pub discriminator: [u8; 8],
+    pub version: u8,
+    pub sender: solana_program::pubkey::Pubkey,
+    pub recipient: solana_program::pubkey::Pubkey,
+    /// Vault the stream was funded from, refunded on cancellation.
+    pub vault: solana_program::pubkey::Pubkey,
+    pub stream_id: u64,
+    pub bump: u8,
+    /// Lamports released per second.
+    pub rate: u64,
+    pub start_time: i64,
+    /// Lamports escrowed when the stream was opened.
+    pub deposited: u64,
+    /// Lamports the recipient has claimed so far.
+    pub withdrawn: u64,
+    pub updated_at: i64,
+}
+
+impl Stream {
+    pub const LEN: usize = 8 + 1 + 32 + 32 + 32 + 8 + 1 + 8 + 8 + 8 + 8 + 8;
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        sender: solana_program::pubkey::Pubkey,
+        recipient: solana_program::pubkey::Pubkey,
+        vault: solana_program::pubkey::Pubkey,
+        stream_id: u64,
+        bump: u8,
+        rate: u64,
+        deposited: u64,
+        timestamp: i64,
+    ) -> Self {
+        Self {
+            discriminator: STREAM_DISCRIMINATOR,
+            version: STREAM_VERSION,
+            sender,
+            recipient,
+            vault,
+            stream_id,
+            bump,
+            rate,
+            start_time: timestamp,
+            deposited,
+            withdrawn: 0,
+            updated_at: timestamp,
+        }
+    }
+
+    pub fn find_address(
+        program_id: &solana_program::pubkey::Pubkey,
+        sender: &solana_program::pubkey::Pubkey,
+        recipient: &solana_program::pubkey::Pubkey,
+        stream_id: u64,
+    ) -> (solana_program::pubkey::Pubkey, u8) {
+        solana_program::pubkey::Pubkey::find_program_address(
+            &[
+                STREAM_SEED,
+                sender.as_ref(),
+                recipient.as_ref(),
+                &stream_id.to_le_bytes(),
+            ],
+            program_id,
+        )
+    }
+
+    pub fn unpack(data: &[u8]) -> Result<Self, solana_program::program_error::ProgramError> {
+        if data.len() < Self::LEN || data[..8] != STREAM_DISCRIMINATOR {
+            return Err(crate::error::VaultError::InvalidAccountDiscriminator.into());
+        }
+
+        if data[8] != STREAM_VERSION {
+            return Err(crate::error::VaultError::UnsupportedVaultVersion.into());
+        }
+
+        Self::try_from_slice(&data[..Self::LEN])
+            .map_err(|_| solana_program::program_error::ProgramError::InvalidAccountData)
+    }
+
+    pub fn pack(&self, data: &mut [u8]) -> solana_program::entrypoint::ProgramResult {
+        if data.len() < Self::LEN {
+            return Err(solana_program::program_error::ProgramError::AccountDataTooSmall);
+        }
+
+        self.serialize(&mut &mut data[..Self::LEN])
+            .map_err(|_| solana_program::program_error::ProgramError::InvalidAccountData)
+    }
+
+    /// Lamports released to the recipient by `timestamp`, claimed or not.
+    pub fn accrued_amount(&self, timestamp: i64) -> u64 {
+        let elapsed = timestamp.saturating_sub(self.start_time).max(0) as u128;
+
+        (elapsed * self.rate as u128).min(self.deposited as u128) as u64
+    }
+
+    /// Lamports the recipient can claim at `timestamp`.
+    pub fn claimable_amount(&self, timestamp: i64) -> u64 {
+        self.accrued_amount(timestamp) - self.withdrawn
+    }
+
+    /// Books everything accrued so far as claimed and returns the amount.
+    pub fn claim(&mut self, timestamp: i64) -> u64 {
+        let amount = self.claimable_amount(timestamp);
+
+        self.withdrawn += amount;
+        self.updated_at = timestamp;
+
+        amount
+    }
+}
+
+/// Owner set and approval threshold of a multisig vault. Lives in a PDA
+/// derived from the vault and is fixed once created.
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub struct Multisig {
+    pub discriminator: [u8; 8],
+    pub version: u8,
+    pub vault: solana_program::pubkey::Pubkey,
+    pub bump: u8,
+    pub threshold: u8,
+    pub owner_count: u8,
+    pub owners: [solana_program::pubkey::Pubkey; MAX_MULTISIG_OWNERS],
+    /// Id of the next proposal. Ids are never reused, so a proposal PDA can
+    /// only ever be created once.
+    pub proposal_count: u64,
+}
+
+impl Multisig {
+    pub const LEN: usize = 8 + 1 + 32 + 1 + 1 + 1 + 32 * MAX_MULTISIG_OWNERS + 8;
+
+    pub fn new(
+        vault: solana_program::pubkey::Pubkey,
+        bump: u8,
+        threshold: u8,
+        owners: &[solana_program::pubkey::Pubkey],
+    ) -> Result<Self, solana_program::program_error::ProgramError> {
+        if !is_valid_signer_set(owners, threshold, MAX_MULTISIG_OWNERS) {
+            return Err(crate::error::VaultError::InvalidMultisigConfig.into());
+        }
+
+        let mut owner_keys = [solana_program::pubkey::Pubkey::default(); MAX_MULTISIG_OWNERS];
+        owner_keys[..owners.len()].copy_from_slice(owners);
+
+        Ok(Self {
+            discriminator: MULTISIG_DISCRIMINATOR,
+            version: MULTISIG_VERSION,
+            vault,
+            bump,
+            threshold,
+            owner_count: owners.len() as u8,
+            owners: owner_keys,
+            proposal_count: 0,
+        })
+    }
+
+    pub fn find_address(
+        program_id: &solana_program::pubkey::Pubkey,
+        vault: &solana_program::pubkey::Pubkey,
+    ) -> (solana_program::pubkey::Pubkey, u8) {
+        solana_program::pubkey::Pubkey::find_program_address(
+            &[MULTISIG_SEED, vault.as_ref()],
+            program_id,
+        )
+    }
+
+    pub fn unpack(data: &[u8]) -> Result<Self, solana_program::program_error::ProgramError> {
+        if data.len() < Self::LEN || data[..8] != MULTISIG_DISCRIMINATOR {
+            return Err(crate::error::VaultError::InvalidAccountDiscriminator.into());
+        }
+
+        if data[8] != MULTISIG_VERSION {
+            return Err(crate::error::VaultError::UnsupportedVaultVersion.into());
+        }
+
+        Self::try_from_slice(&data[..Self::LEN])
+            .map_err(|_| solana_program::program_error::ProgramError::InvalidAccountData)
+    }
+
+    pub fn pack(&self, data: &mut [u8]) -> solana_program::entrypoint::ProgramResult {
+        if data.len() < Self::LEN {
+            return Err(solana_program::program_error::ProgramError::AccountDataTooSmall);
+        }
+
+        self.serialize(&mut &mut data[..Self::LEN])
+            .map_err(|_| solana_program::program_error::ProgramError::InvalidAccountData)
+    }
+
+    pub fn owners(&self) -> &[solana_program::pubkey::Pubkey] {
+        &self.owners[..self.owner_count as usize]
+    }
+
+    /// Position of `key` in the owner set, which is its approval bit.
+    pub fn owner_index(
+        &self,
+        key: &solana_program::pubkey::Pubkey,
+    ) -> Result<usize, solana_program::program_error::ProgramError> {
+        self.owners()
+            .iter()
+            .position(|owner| owner == key)
+            .ok_or_else(|| crate::error::VaultError::NotMultisigOwner.into())
+    }
+}
+
+/// A withdrawal from a multisig vault waiting for approvals. Lives in a PDA
+/// derived from the vault and a sequential proposal id.
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub struct Proposal {
+    pub discriminator: [u8; 8],
+    pub version: u8,
+    pub vault: solana_program::pubkey::Pubkey,
+    pub proposal_id: u64,
+    pub bump: u8,
+    pub proposer: solana_program::pubkey::Pubkey,
+    pub destination: solana_program::pubkey::Pubkey,
+    pub amount: u64,
+    /// Bit `i` is set once owner `i` of the [`Multisig`] has approved.
+    pub approvals: u16,
+    pub expires_at: i64,
+    pub executed: bool,
+    pub created_at: i64,
+    pub updated_at: i64,
+}
+
+impl Proposal {
+    pub const LEN: usize = 8 + 1 + 32 + 8 + 1 + 32 + 32 + 8 + 2 + 8 + 1 + 8 + 8;
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        vault: solana_program::pubkey::Pubkey,
+        proposal_id: u64,
+        bump: u8,
+        proposer: solana_program::pubkey::Pubkey,
+        destination: solana_program::pubkey::Pubkey,
+        amount: u64,
+        expires_at: i64,
+        timestamp: i64,
+    ) -> Self {
+        Self {
+            discriminator: PROPOSAL_DISCRIMINATOR,
+            version: PROPOSAL_VERSION,
+            vault,
+            proposal_id,
+            bump,
+            proposer,
+            destination,
+            amount,
+            approvals: 0,
+            expires_at,
+            executed: false,
+            created_at: timestamp,
+            updated_at: timestamp,
+        }
+    }
+
+    pub fn find_address(
+        program_id: &solana_program::pubkey::Pubkey,
+        vault: &solana_program::pubkey::Pubkey,
+        proposal_id: u64,
+    ) -> (solana_program::pubkey::Pubkey, u8) {
+        solana_program::pubkey::Pubkey::find_program_address(
+            &[PROPOSAL_SEED, vault.as_ref(), &proposal_id.to_le_bytes()],
+            program_id,
+        )
+    }
+
+    pub fn unpack(data: &[u8]) -> Result<Self, solana_program::program_error::ProgramError> {
+        if data.len() < Self::LEN || data[..8] != PROPOSAL_DISCRIMINATOR {
+            return Err(crate::error::VaultError::InvalidAccountDiscriminator.into());
+        }
+
+        if data[8] != PROPOSAL_VERSION {
+            return Err(crate::error::VaultError::UnsupportedVaultVersion.into());
+        }
+
+        Self::try_from_slice(&data[..Self::LEN])
+            .map_err(|_| solana_program::program_error::ProgramError::InvalidAccountData)
+    }
+
+    pub fn pack(&self, data: &mut [u8]) -> solana_program::entrypoint::ProgramResult {
+        if data.len() < Self::LEN {
+            return Err(solana_program::program_error::ProgramError::AccountDataTooSmall);
+        }
+
+        self.serialize(&mut &mut data[..Self::LEN])
+            .map_err(|_| solana_program::program_error::ProgramError::InvalidAccountData)
+    }
+
+    /// Fails once the proposal has been executed or has expired.
+    pub fn ensure_pending(&self, timestamp: i64) -> solana_program::entrypoint::ProgramResult {
+        if self.executed {
+            return Err(crate::error::VaultError::ProposalExecuted.into());
+        }
+
+        if timestamp >= self.expires_at {
+            return Err(crate::error::VaultError::ProposalExpired.into());
+        }
+
+        Ok(())
+    }
+
+    pub fn approve(&mut self, owner_index: usize, timestamp: i64) {
+        self.approvals |= 1 << owner_index;
+        self.updated_at = timestamp;
+    }
+
+    pub fn approval_count(&self) -> u32 {
+        self.approvals.count_ones()
+    }
+}
+
+/// Lamports a delegate may withdraw from a vault without the owner's
+/// signature. Lives in a PDA derived from the vault and the delegate.
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub struct Allowance {
+    pub discriminator: [u8; 8],
+    pub version: u8,
+    pub vault: solana_program::pubkey::Pubkey,
+    pub delegate: solana_program::pubkey::Pubkey,
+    pub bump: u8,
+    /// Lamports the delegate can still withdraw.
+    pub amount: u64,
+    /// Slot from which the allowance can no longer be used, zero if it
+    /// never expires.
+    pub expiry_slot: u64,
+    pub created_at: i64,
+    pub updated_at: i64,
+    /// [`Vault::created_slot`] of the vault the allowance was granted on.
+    pub vault_created_slot: u64,
+}
+
+impl Allowance {
+    pub const LEN: usize = 8 + 1 + 32 + 32 + 1 + 8 + 8 + 8 + 8 + 8;
+
+    pub fn new(
+        vault: solana_program::pubkey::Pubkey,
+        delegate: solana_program::pubkey::Pubkey,
+        bump: u8,
+        vault_created_slot: u64,
+        timestamp: i64,
+    ) -> Self {
+        Self {
+            discriminator: ALLOWANCE_DISCRIMINATOR,
+            version: ALLOWANCE_VERSION,
+            vault,
+            delegate,
+            bump,
+            amount: 0,
+            expiry_slot: 0,
+            created_at: timestamp,
+            updated_at: timestamp,
+            vault_created_slot,
+        }
+    }
+
+    pub fn find_address(
+        program_id: &solana_program::pubkey::Pubkey,
+        vault: &solana_program::pubkey::Pubkey,
+        delegate: &solana_program::pubkey::Pubkey,
+    ) -> (solana_program::pubkey::Pubkey, u8) {
+        solana_program::pubkey::Pubkey::find_program_address(
+            &[ALLOWANCE_SEED, vault.as_ref(), delegate.as_ref()],
+            program_id,
+        )
+    }
+
+    pub fn unpack(data: &[u8]) -> Result<Self, solana_program::program_error::ProgramError> {
+        if data.len() < Self::LEN || data[..8] != ALLOWANCE_DISCRIMINATOR {
+            return Err(crate::error::VaultError::InvalidAccountDiscriminator.into());
+        }
+
+        if data[8] != ALLOWANCE_VERSION {
+            return Err(crate::error::VaultError::UnsupportedVaultVersion.into());
+        }
+
+        Self::try_from_slice(&data[..Self::LEN])
+            .map_err(|_| solana_program::program_error::ProgramError::InvalidAccountData)
+    }
+
+    pub fn pack(&self, data: &mut [u8]) -> solana_program::entrypoint::ProgramResult {
+        if data.len() < Self::LEN {
+            return Err(solana_program::program_error::ProgramError::AccountDataTooSmall);
+        }
+
+        self.serialize(&mut &mut data[..Self::LEN])
+            .map_err(|_| solana_program::program_error::ProgramError::InvalidAccountData)
+    }
+
+    /// Takes `amount` out of the allowance, failing once it has expired or
+    /// if it does not cover the amount.
+    pub fn spend(
+        &mut self,
+        amount: u64,
+        clock: &solana_program::clock::Clock,
+    ) -> solana_program::entrypoint::ProgramResult {
+        if self.expiry_slot != 0 && clock.slot >= self.expiry_slot {
+            return Err(crate::error::VaultError::AllowanceExpired.into());
+        }
+
+        self.amount = self
+            .amount
+            .checked_sub(amount)
+            .ok_or(crate::error::VaultError::InsufficientAllowance)?;
+        self.updated_at = clock.unix_timestamp;
+
+        Ok(())
+    }
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Clone, Copy, Debug, Default, PartialEq)]
+pub struct AllowlistEntry {
+    pub destination: solana_program::pubkey::Pubkey,
+    /// When withdrawals to `destination` start being allowed.
+    pub active_at: i64,
+}
+
+/// Destinations a vault may withdraw to while its allowlist is enabled.
+/// Lives in a PDA derived from the vault.
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub struct Allowlist {
+    pub discriminator: [u8; 8],
+    pub version: u8,
+    pub vault: solana_program::pubkey::Pubkey,
+    pub bump: u8,
+    pub entry_count: u8,
+    pub entries: [AllowlistEntry; MAX_ALLOWLIST_ENTRIES],
+    /// [`Vault::created_slot`] of the vault the allowlist belongs to.
+    pub vault_created_slot: u64,
+}
+
+impl Allowlist {
+    pub const LEN: usize = 8 + 1 + 32 + 1 + 1 + (32 + 8) * MAX_ALLOWLIST_ENTRIES + 8;
+
+    pub fn new(vault: solana_program::pubkey::Pubkey, bump: u8, vault_created_slot: u64) -> Self {
+        Self {
+            discriminator: ALLOWLIST_DISCRIMINATOR,
+            version: ALLOWLIST_VERSION,
+            vault,
+            bump,
+            entry_count: 0,
+            entries: [AllowlistEntry::default(); MAX_ALLOWLIST_ENTRIES],
+            vault_created_slot,
+        }
+    }
+
+    pub fn find_address(
+        program_id: &solana_program::pubkey::Pubkey,
+        vault: &solana_program::pubkey::Pubkey,
+    ) -> (solana_program::pubkey::Pubkey, u8) {
+        solana_program::pubkey::Pubkey::find_program_address(
+            &[ALLOWLIST_SEED, vault.as_ref()],
+            program_id,
+        )
+    }
+
+    pub fn unpack(data: &[u8]) -> Result<Self, solana_program::program_error::ProgramError> {
+        if data.len() < Self::LEN || data[..8] != ALLOWLIST_DISCRIMINATOR {
+            return Err(crate::error::VaultError::InvalidAccountDiscriminator.into());
+        }
+
+        if data[8] != ALLOWLIST_VERSION {
+            return Err(crate::error::VaultError::UnsupportedVaultVersion.into());
+        }
+
+        Self::try_from_slice(&data[..Self::LEN])
+            .map_err(|_| solana_program::program_error::ProgramError::InvalidAccountData)
+    }
+
+    pub fn pack(&self, data: &mut [u8]) -> solana_program::entrypoint::ProgramResult {
+        if data.len() < Self::LEN {
+            return Err(solana_program::program_error::ProgramError::AccountDataTooSmall);
+        }
+
+        self.serialize(&mut &mut data[..Self::LEN])
+            .map_err(|_| solana_program::program_error::ProgramError::InvalidAccountData)
+    }
+
+    pub fn entries(&self) -> &[AllowlistEntry] {
+        &self.entries[..self.entry_count as usize]
+    }
+
+    pub fn add(
+        &mut self,
+        destination: solana_program::pubkey::Pubkey,
+        active_at: i64,
+    ) -> solana_program::entrypoint::ProgramResult {
+        if self
+            .entries()
+            .iter()
+            .any(|entry| entry.destination == destination)
+        {
+            return Err(crate::error::VaultError::DestinationAlreadyListed.into());
+        }
+
+        if self.entry_count as usize == MAX_ALLOWLIST_ENTRIES {
+            return Err(crate::error::VaultError::AllowlistFull.into());
+        }
+
+        self.entries[self.entry_count as usize] = AllowlistEntry {
+            destination,
+            active_at,
+        };
+        self.entry_count += 1;
+
+        Ok(())
+    }
+
+    pub fn remove(
+        &mut self,
+        destination: &solana_program::pubkey::Pubkey,
+    ) -> solana_program::entrypoint::ProgramResult {
+        let index = self
+            .entries()
+            .iter()
+            .position(|entry| entry.destination == *destination)
+            .ok_or(crate::error::VaultError::DestinationNotAllowed)?;
+
+        self.entry_count -= 1;
+        self.entries[index] = self.entries[self.entry_count as usize];
+        self.entries[self.entry_count as usize] = AllowlistEntry::default();
+
+        Ok(())
+    }
+
+    pub fn is_allowed(&self, destination: &solana_program::pubkey::Pubkey, timestamp: i64) -> bool {
+        self.entries()
+            .iter()
+            .any(|entry| entry.destination == *destination && timestamp >= entry.active_at)
+    }
+}
+
+/// Recovery guardians of a vault and the authority rotation they are voting
+/// on, if any. Lives in a PDA derived from the vault.
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub struct Guardians {
+    pub discriminator: [u8; 8],
+    pub version: u8,
+    pub vault: solana_program::pubkey::Pubkey,
+    pub bump: u8,
+    pub threshold: u8,
+    pub guardian_count: u8,
+    pub guardians: [solana_program::pubkey::Pubkey; MAX_GUARDIANS],
+    /// Authority guardian `i` votes to rotate to, the default key if none.
+    pub votes: [solana_program::pubkey::Pubkey; MAX_GUARDIANS],
+    /// Authority the pending recovery rotates to, the default key if none.
+    pub new_authority: solana_program::pubkey::Pubkey,
+    /// When the recovery can be executed, zero until the threshold is met.
+    pub ready_at: i64,
+    /// [`Vault::created_slot`] of the vault the guardians protect.
+    pub vault_created_slot: u64,
+}
+
+impl Guardians {
+    pub const LEN: usize =
+        8 + 1 + 32 + 1 + 1 + 1 + 32 * MAX_GUARDIANS + 32 * MAX_GUARDIANS + 32 + 8 + 8;
+
+    pub fn new(
+        vault: solana_program::pubkey::Pubkey,
+        bump: u8,
+        vault_created_slot: u64,
+        threshold: u8,
+        guardians: &[solana_program::pubkey::Pubkey],
+    ) -> Result<Self, solana_program::program_error::ProgramError> {
+        if !is_valid_signer_set(guardians, threshold, MAX_GUARDIANS) {
+            return Err(crate::error::VaultError::InvalidGuardianConfig.into());
+        }
+
+        let mut guardian_keys = [solana_program::pubkey::Pubkey::default(); MAX_GUARDIANS];
+        guardian_keys[..guardians.len()].copy_from_slice(guardians);
+
+        Ok(Self {
+            discriminator: GUARDIANS_DISCRIMINATOR,
+            version: GUARDIANS_VERSION,
+            vault,
+            bump,
+            threshold,
+            guardian_count: guardians.len() as u8,
+            guardians: guardian_keys,
+            votes: [solana_program::pubkey::Pubkey::default(); MAX_GUARDIANS],
+            new_authority: solana_program::pubkey::Pubkey::default(),
+            ready_at: 0,
+            vault_created_slot,
+        })
+    }
+
+    pub fn find_address(
+        program_id: &solana_program::pubkey::Pubkey,
+        vault: &solana_program::pubkey::Pubkey,
+    ) -> (solana_program::pubkey::Pubkey, u8) {
+        solana_program::pubkey::Pubkey::find_program_address(
+            &[GUARDIANS_SEED, vault.as_ref()],
+            program_id,
+        )
+    }
+
+    pub fn unpack(data: &[u8]) -> Result<Self, solana_program::program_error::ProgramError> {
+        if data.len() < Self::LEN || data[..8] != GUARDIANS_DISCRIMINATOR {
+            return Err(crate::error::VaultError::InvalidAccountDiscriminator.into());
+        }
+
+        if data[8] != GUARDIANS_VERSION {
+            return Err(crate::error::VaultError::UnsupportedVaultVersion.into());
+        }
+
+        Self::try_from_slice(&data[..Self::LEN])
+            .map_err(|_| solana_program::program_error::ProgramError::InvalidAccountData)
+    }
+
+    pub fn pack(&self, data: &mut [u8]) -> solana_program::entrypoint::ProgramResult {
+        if data.len() < Self::LEN {
+            return Err(solana_program::program_error::ProgramError::AccountDataTooSmall);
+        }
+
+        self.serialize(&mut &mut data[..Self::LEN])
+            .map_err(|_| solana_program::program_error::ProgramError::InvalidAccountData)
+    }
+
+    pub fn guardians(&self) -> &[solana_program::pubkey::Pubkey] {
+        &self.guardians[..self.guardian_count as usize]
+    }
+
+    pub fn guardian_index(
+        &self,
+        key: &solana_program::pubkey::Pubkey,
+    ) -> Result<usize, solana_program::program_error::ProgramError> {
+        self.guardians()
+            .iter()
+            .position(|guardian| guardian == key)
+            .ok_or_else(|| crate::error::VaultError::NotGuardian.into())
+    }
+
+    /// Records a guardian's vote for `new_authority`, replacing any earlier
+    /// vote of theirs. A key becomes the pending recovery, and its timelock
+    /// starts, once the threshold of guardians votes for it; it stops being
+    /// pending if votes move away and leave it short of the threshold.
+    pub fn approve(
+        &mut self,
+        guardian_index: usize,
+        new_authority: solana_program::pubkey::Pubkey,
+        timestamp: i64,
+    ) -> solana_program::entrypoint::ProgramResult {
+        if new_authority == solana_program::pubkey::Pubkey::default() {
+            return Err(crate::error::VaultError::InvalidRecoveryAuthority.into());
+        }
+
+        self.votes[guardian_index] = new_authority;
+
+        if self.new_authority != solana_program::pubkey::Pubkey::default()
+            && self.approval_count(&self.new_authority) < self.threshold as u32
+        {
+            self.new_authority = solana_program::pubkey::Pubkey::default();
+            self.ready_at = 0;
+        }
+
+        if self.new_authority == solana_program::pubkey::Pubkey::default()
+            && self.approval_count(&new_authority) >= self.threshold as u32
+        {
+            self.new_authority = new_authority;
+            self.ready_at = timestamp + RECOVERY_TIMELOCK;
+        }
+
+        Ok(())
+    }
+
+    /// Number of guardians currently voting for `new_authority`.
+    pub fn approval_count(&self, new_authority: &solana_program::pubkey::Pubkey) -> u32 {
+        self.votes[..self.guardian_count as usize]
+            .iter()
+            .filter(|vote| *vote == new_authority)
+            .count() as u32
+    }
+
+    /// Returns the new authority once the recovery can be executed.
+    pub fn ready_authority(
+        &self,
+        timestamp: i64,
+    ) -> Result<solana_program::pubkey::Pubkey, solana_program::program_error::ProgramError> {
+        if self.new_authority == solana_program::pubkey::Pubkey::default() {
+            return Err(crate::error::VaultError::NoPendingRecovery.into());
+        }
+
+        if self.ready_at == 0 || timestamp < self.ready_at {
+            return Err(crate::error::VaultError::RecoveryNotReady.into());
+        }
+
+        Ok(self.new_authority)
+    }
+
+    pub fn cancel(&mut self) {
+        self.votes = [solana_program::pubkey::Pubkey::default(); MAX_GUARDIANS];
+        self.new_authority = solana_program::pubkey::Pubkey::default();
+        self.ready_at = 0;
+    }
+}
+
+/// Program-wide settings, held in a singleton PDA that the upgrade
+/// authority initializes once.
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub struct Config {
+    pub discriminator: [u8; 8],
+    pub version: u8,
+    pub bump: u8,
+    /// Key allowed to pause the program and change the fees.
+    pub admin: solana_program::pubkey::Pubkey,
+    /// While set, every instruction except `Balance` fails.
+    pub paused: bool,
+    pub deposit_fee_bps: u16,
+    pub withdraw_fee_bps: u16,
+    /// Account receiving the fees.
+    pub treasury: solana_program::pubkey::Pubkey,
+    pub updated_at: i64,
+}
+
+impl Config {
+    pub const LEN: usize = 8 + 1 + 1 + 32 + 1 + 2 + 2 + 32 + 8;
+
+    pub fn new(
+        bump: u8,
+        admin: solana_program::pubkey::Pubkey,
+        treasury: solana_program::pubkey::Pubkey,
+        timestamp: i64,
+    ) -> Self {
+        Self {
+            discriminator: CONFIG_DISCRIMINATOR,
+            version: CONFIG_VERSION,
+            bump,
+            admin,
+            paused: false,
+            deposit_fee_bps: 0,
+            withdraw_fee_bps: 0,
+            treasury,
+            updated_at: timestamp,
+        }
+    }
+
+    pub fn find_address(
+        program_id: &solana_program::pubkey::Pubkey,
+    ) -> (solana_program::pubkey::Pubkey, u8) {
+        solana_program::pubkey::Pubkey::find_program_address(&[CONFIG_SEED], program_id)
+    }
+
+    pub fn unpack(data: &[u8]) -> Result<Self, solana_program::program_error::ProgramError> {
+        if data.len() < Self::LEN || data[..8] != CONFIG_DISCRIMINATOR {
+            return Err(crate::error::VaultError::InvalidAccountDiscriminator.into());
+        }
+
+        if data[8] != CONFIG_VERSION {
+            return Err(crate::error::VaultError::UnsupportedVaultVersion.into());
+        }
+
+        Self::try_from_slice(&data[..Self::LEN])
+            .map_err(|_| solana_program::program_error::ProgramError::InvalidAccountData)
+    }
+
+    pub fn pack(&self, data: &mut [u8]) -> solana_program::entrypoint::ProgramResult {
+        if data.len() < Self::LEN {
+            return Err(solana_program::program_error::ProgramError::AccountDataTooSmall);
+        }
+
+        self.serialize(&mut &mut data[..Self::LEN])
+            .map_err(|_| solana_program::program_error::ProgramError::InvalidAccountData)
+    }
+
+    pub fn set_fees(
+        &mut self,
+        deposit_fee_bps: u16,
+        withdraw_fee_bps: u16,
+    ) -> solana_program::entrypoint::ProgramResult {
+        if deposit_fee_bps > MAX_FEE_BPS || withdraw_fee_bps > MAX_FEE_BPS {
+            return Err(crate::error::VaultError::InvalidFee.into());
+        }
+
+        self.deposit_fee_bps = deposit_fee_bps;
+        self.withdraw_fee_bps = withdraw_fee_bps;
+
+        Ok(())
+    }
+
+    /// Fee charged on depositing `amount`, rounded down.
+    pub fn deposit_fee(&self, amount: u64) -> u64 {
+        fee_amount(amount, self.deposit_fee_bps)
+    }
+
+    /// Fee charged on withdrawing `amount`, rounded down.
+    pub fn withdraw_fee(&self, amount: u64) -> u64 {
+        fee_amount(amount, self.withdraw_fee_bps)
+    }
+}
+
+fn fee_amount(amount: u64, fee_bps: u16) -> u64 {
+    (amount as u128 * fee_bps as u128 / MAX_FEE_BPS as u128) as u64
+}
+
+/// Whether `keys` is a non-empty set of at most `max_len` distinct keys that
+/// `threshold` of them can act for.
+fn is_valid_signer_set(
+    keys: &[solana_program::pubkey::Pubkey],
+    threshold: u8,
+    max_len: usize,
+) -> bool {
+    let has_duplicates = keys
+        .iter()
+        .enumerate()
+        .any(|(index, key)| keys[..index].contains(key));
+
+    !keys.is_empty()
+        && keys.len() <= max_len
+        && !has_duplicates
+        && threshold != 0
+        && threshold as usize <= keys.len()
+}
+
+#[cfg(test)]
+mod state_tests {
+    use super::*;
+
+    #[test]
+    fn test_vault_roundtrip() {
+        let mut vault = Vault::new(
+            solana_program::pubkey::Pubkey::new_unique(),
+            254,
+            b"savings",
+            100,
+        );
+        vault.deposit(500, 110).unwrap();
+        vault.withdraw(200, 120).unwrap();
+
+        let mut data = [0u8; Vault::LEN];
+        vault.pack(&mut data).unwrap();
+
+        let unpacked = Vault::unpack(&data).unwrap();
+
+        assert_eq!(unpacked, vault);
+        assert_eq!(unpacked.balance, 300);
+        assert_eq!(unpacked.total_deposited, 500);
+        assert_eq!(unpacked.total_withdrawn, 200);
+        assert_eq!(unpacked.created_at, 100);
+        assert_eq!(unpacked.updated_at, 120);
+        assert_eq!(unpacked.name(), b"savings");
+        assert_eq!(
+            &data[VAULT_OWNER_OFFSET..VAULT_OWNER_OFFSET + 32],
+            vault.owner.as_ref(),
+        );
+    }
+
+    #[test]
+    fn test_vesting_schedule() {
+        let terms = crate::instruction::VestingTerms {
+            start: 1_000,
+            cliff: 1_250,
+            end: 2_000,
+            revocable: true,
+        };
+        let schedule =
+            VestingSchedule::new(solana_program::pubkey::Pubkey::new_unique(), &terms, 1_000)
+                .unwrap();
+
+        assert_eq!(schedule.vested_amount(0), 0);
+        assert_eq!(schedule.vested_amount(1_249), 0);
+        assert_eq!(schedule.vested_amount(1_250), 250);
+        assert_eq!(schedule.vested_amount(1_500), 500);
+        assert_eq!(schedule.unvested_amount(1_500), 500);
+        assert_eq!(schedule.vested_amount(2_000), 1_000);
+        assert_eq!(schedule.unvested_amount(i64::MAX), 0);
+        assert_eq!(VestingSchedule::default().unvested_amount(0), 0);
+
+        let terms = crate::instruction::VestingTerms {
+            start: i64::MIN,
+            cliff: i64::MIN,
+            end: -1,
+            revocable: false,
+        };
+        let schedule =
+            VestingSchedule::new(solana_program::pubkey::Pubkey::new_unique(), &terms, 1_000)
+                .unwrap();
+
+        assert_eq!(schedule.vested_amount(i64::MIN), 0);
+        assert_eq!(schedule.vested_amount(-2), 999);
+        assert_eq!(schedule.vested_amount(-1), 1_000);
+
+        for (start, cliff, end) in [
+            (1_000, 900, 2_000),
+            (1_000, 2_100, 2_000),
+            (1_000, 1_000, 1_000),
+            (i64::MIN, 0, 1),
+            (i64::MIN, i64::MIN, i64::MAX),
+        ] {
+            let terms = crate::instruction::VestingTerms {
+                start,
+                cliff,
+                end,
+                revocable: false,
+            };
+
+            assert_eq!(
+                VestingSchedule::new(solana_program::pubkey::Pubkey::new_unique(), &terms, 1_000),
+                Err(crate::error::VaultError::InvalidVestingSchedule.into()),
+            );
+        }
+    }
+
+    #[test]
+    fn test_stream_accrual() {
+        let mut stream = Stream::new(
+            solana_program::pubkey::Pubkey::new_unique(),
+            solana_program::pubkey::Pubkey::new_unique(),
+            solana_program::pubkey::Pubkey::new_unique(),
+            7,
+            253,
+            10,
+            1_005,
+            1_000,
+        );
+
+        assert_eq!(stream.accrued_amount(999), 0);
+        assert_eq!(stream.accrued_amount(1_030), 300);
+        assert_eq!(stream.claim(1_030), 300);
+        assert_eq!(stream.claimable_amount(1_030), 0);
+        assert_eq!(stream.claimable_amount(1_050), 200);
+        assert_eq!(stream.accrued_amount(i64::MAX), 1_005);
+        assert_eq!(stream.claim(i64::MAX), 705);
+
+        let mut data = [0u8; Stream::LEN];
+        stream.pack(&mut data).unwrap();
+
+        assert_eq!(Stream::unpack(&data).unwrap(), stream);
+        assert_eq!(
+            Vault::unpack(&data),
+            Err(crate::error::VaultError::InvalidAccountDiscriminator.into()),
+        );
+    }
+
+    #[test]
+    fn test_multisig_config() {
+        let vault = solana_program::pubkey::Pubkey::new_unique();
+        let owners = [
+            solana_program::pubkey::Pubkey::new_unique(),
+            solana_program::pubkey::Pubkey::new_unique(),
+            solana_program::pubkey::Pubkey::new_unique(),
+        ];
+
+        let multisig = Multisig::new(vault, 250, 2, &owners).unwrap();
+
+        assert_eq!(multisig.owners(), &owners);
+        assert_eq!(multisig.owner_index(&owners[2]), Ok(2));
+        assert_eq!(
+            multisig.owner_index(&vault),
+            Err(crate::error::VaultError::NotMultisigOwner.into()),
+        );
+
+        let mut data = [0u8; Multisig::LEN];
+        multisig.pack(&mut data).unwrap();
+
+        assert_eq!(Multisig::unpack(&data).unwrap(), multisig);
+
+        for (threshold, owners) in [
+            (0, &owners[..]),
+            (4, &owners[..]),
+            (1, &[][..]),
+            (1, &[owners[0], owners[1], owners[0]][..]),
+            (1, &[owners[0]; MAX_MULTISIG_OWNERS + 1][..]),
+        ] {
+            assert_eq!(
+                Multisig::new(vault, 250, threshold, owners),
+                Err(crate::error::VaultError::InvalidMultisigConfig.into()),
+            );
+        }
+    }
+
+    #[test]
+    fn test_withdrawal_limit() {
+        let mut withdrawal_limit = WithdrawalLimit::default();
+        withdrawal_limit.consume(u64::MAX, 0).unwrap();
+
+        withdrawal_limit.set(100, 1_000);
+        withdrawal_limit.consume(60, 1_000).unwrap();
+        assert_eq!(
+            withdrawal_limit.consume(50, 1_001),
+            Err(crate::error::VaultError::WithdrawalLimitExceeded.into()),
+        );
+        withdrawal_limit
+            .consume(50, 1_000 + WITHDRAWAL_LIMIT_WINDOW)
+            .unwrap();
+
+        withdrawal_limit.set(0, 2_000);
+        assert_eq!(withdrawal_limit.limit, 100);
+        withdrawal_limit.set(80, 2_000);
+        assert_eq!(withdrawal_limit.limit, 80);
+        assert_eq!(withdrawal_limit.pending_at, 0);
+
+        withdrawal_limit.set(500, 3_000);
+        assert_eq!(withdrawal_limit.limit, 80);
+        withdrawal_limit
+            .consume(450, 3_000 + WITHDRAWAL_LIMIT_DELAY)
+            .unwrap();
+        assert_eq!(withdrawal_limit.limit, 500);
+    }
+
+    #[test]
+    fn test_withdrawal_limit_rolls() {
+        let mut withdrawal_limit = WithdrawalLimit::default();
+        withdrawal_limit.set(100, 0);
+
+        withdrawal_limit.consume(1, 0).unwrap();
+        withdrawal_limit
+            .consume(99, WITHDRAWAL_LIMIT_WINDOW - 1)
+            .unwrap();
+        assert_eq!(
+            withdrawal_limit.consume(100, WITHDRAWAL_LIMIT_WINDOW),
+            Err(crate::error::VaultError::WithdrawalLimitExceeded.into()),
+        );
+
+        let half_window = WITHDRAWAL_LIMIT_WINDOW - 1 + WITHDRAWAL_LIMIT_WINDOW / 2;
+        assert_eq!(
+            withdrawal_limit.consume(52, half_window),
+            Err(crate::error::VaultError::WithdrawalLimitExceeded.into()),
+        );
+        withdrawal_limit.consume(51, half_window).unwrap();
+        assert_eq!(withdrawal_limit.used, 100);
+
+        withdrawal_limit.set(0, half_window);
+        withdrawal_limit
+            .consume(u64::MAX, half_window + WITHDRAWAL_LIMIT_DELAY)
+            .unwrap();
+        assert_eq!(withdrawal_limit, WithdrawalLimit::default());
+    }
+
+    #[test]
+    fn test_allowlist_entries() {
+        let destinations = [
+            solana_program::pubkey::Pubkey::new_unique(),
+            solana_program::pubkey::Pubkey::new_unique(),
+        ];
+        let mut allowlist = Allowlist::new(solana_program::pubkey::Pubkey::new_unique(), 252, 7);
+
+        allowlist.add(destinations[0], 100).unwrap();
+        allowlist.add(destinations[1], 200).unwrap();
+
+        assert_eq!(
+            allowlist.add(destinations[0], 300),
+            Err(crate::error::VaultError::DestinationAlreadyListed.into()),
+        );
+        assert!(!allowlist.is_allowed(&destinations[0], 99));
+        assert!(allowlist.is_allowed(&destinations[0], 100));
+        assert!(!allowlist.is_allowed(&destinations[1], 100));
+
+        allowlist.remove(&destinations[0]).unwrap();
+
+        assert_eq!(allowlist.entries().len(), 1);
+        assert!(!allowlist.is_allowed(&destinations[0], 100));
+        assert!(allowlist.is_allowed(&destinations[1], 200));
+
+        let mut data = [0u8; Allowlist::LEN];
+        allowlist.pack(&mut data).unwrap();
+
+        assert_eq!(Allowlist::unpack(&data).unwrap(), allowlist);
+
+        for _ in 1..MAX_ALLOWLIST_ENTRIES {
+            allowlist
+                .add(solana_program::pubkey::Pubkey::new_unique(), 0)
+                .unwrap();
+        }
+
+        assert_eq!(
+            allowlist.add(solana_program::pubkey::Pubkey::new_unique(), 0),
+            Err(crate::error::VaultError::AllowlistFull.into()),
+        );
+    }
+
+    #[test]
+    fn test_guardian_recovery() {
+        let guardian_keys = [
+            solana_program::pubkey::Pubkey::new_unique(),
+            solana_program::pubkey::Pubkey::new_unique(),
+            solana_program::pubkey::Pubkey::new_unique(),
+        ];
+        let new_authority = solana_program::pubkey::Pubkey::new_unique();
+        let mut guardians = Guardians::new(
+            solana_program::pubkey::Pubkey::new_unique(),
+            251,
+            7,
+            2,
+            &guardian_keys,
+        )
+        .unwrap();
+
+        assert_eq!(
+            guardians.ready_authority(0),
+            Err(crate::error::VaultError::NoPendingRecovery.into()),
+        );
+
+        let conflicting_authority = solana_program::pubkey::Pubkey::new_unique();
+
+        assert_eq!(
+            guardians.approve(0, solana_program::pubkey::Pubkey::default(), 50),
+            Err(crate::error::VaultError::InvalidRecoveryAuthority.into()),
+        );
+
+        // A guardian backing another key doesn't block the others.
+        guardians.approve(0, conflicting_authority, 100).unwrap();
+        guardians.approve(1, new_authority, 200).unwrap();
+        assert_eq!(guardians.approval_count(&conflicting_authority), 1);
+        assert_eq!(guardians.approval_count(&new_authority), 1);
+        assert_eq!(guardians.ready_at, 0);
+
+        guardians.approve(2, new_authority, 300).unwrap();
+        assert_eq!(guardians.new_authority, new_authority);
+        assert_eq!(guardians.ready_at, 300 + RECOVERY_TIMELOCK);
+
+        // Repeat and dissenting votes leave the running timelock alone.
+        guardians.approve(2, new_authority, 400).unwrap();
+        guardians.approve(0, conflicting_authority, 500).unwrap();
+        assert_eq!(guardians.new_authority, new_authority);
+        assert_eq!(guardians.ready_at, 300 + RECOVERY_TIMELOCK);
+        assert_eq!(
+            guardians.ready_authority(299 + RECOVERY_TIMELOCK),
+            Err(crate::error::VaultError::RecoveryNotReady.into()),
+        );
+        assert_eq!(
+            guardians.ready_authority(300 + RECOVERY_TIMELOCK),
+            Ok(new_authority),
+        );
+
+        // A guardian switching their vote can drop the pending key and
+        // start a fresh timelock for another one.
+        guardians.approve(1, conflicting_authority, 600).unwrap();
+        assert_eq!(guardians.new_authority, conflicting_authority);
+        assert_eq!(guardians.ready_at, 600 + RECOVERY_TIMELOCK);
+
+        guardians.cancel();
+        assert_eq!(guardians.approval_count(&conflicting_authority), 0);
+        assert_eq!(
+            guardians.ready_authority(i64::MAX),
+            Err(crate::error::VaultError::NoPendingRecovery.into()),
+        );
+    }
+
+    #[test]
+    fn test_config_fees() {
+        let mut config = Config::new(
+            254,
+            solana_program::pubkey::Pubkey::new_unique(),
+            solana_program::pubkey::Pubkey::new_unique(),
+            0,
+        );
+
+        assert_eq!(
+            config.set_fees(MAX_FEE_BPS + 1, 0),
+            Err(crate::error::VaultError::InvalidFee.into()),
+        );
+        assert_eq!(config.deposit_fee(1_000_000), 0);
+
+        config.set_fees(25, MAX_FEE_BPS).unwrap();
+        assert_eq!(config.deposit_fee(1_000_000), 2_500);
+        assert_eq!(config.deposit_fee(399), 0);
+        assert_eq!(config.withdraw_fee(u64::MAX), u64::MAX);
+    }
+
+    #[test]
+    fn test_vault_rejects_foreign_data() {
+        let legacy = 1_000u64.to_le_bytes();
+
+        assert_eq!(
+            Vault::unpack(&legacy),
+            Err(crate::error::VaultError::InvalidAccountDiscriminator.into()),
+        );
+
+        let mut data = [0u8; Vault::LEN];
+        Vault::new(solana_program::pubkey::Pubkey::new_unique(), 255, &[], 0)
+            .pack(&mut data)
+            .unwrap();
+        data[8] = VAULT_VERSION + 1;
+
+        assert_eq!(
+            Vault::unpack(&data),
+            Err(crate::error::VaultError::UnsupportedVaultVersion.into()),
+        );
+    }
+}
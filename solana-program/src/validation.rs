@@ -0,0 +1,130 @@
+use crate::error::VaultError;
+
+/// Fails unless `account` signed the transaction.
+pub fn assert_signer(
+    account: &solana_program::account_info::AccountInfo,
+) -> solana_program::entrypoint::ProgramResult {
+    if !account.is_signer {
+        solana_program::msg!("Missing signature: {}", account.key);
+
+        return Err(VaultError::MissingSignature.into());
+    }
+
+    Ok(())
+}
+
+/// Fails unless `account` was passed as writable.
+pub fn assert_writable(
+    account: &solana_program::account_info::AccountInfo,
+) -> solana_program::entrypoint::ProgramResult {
+    if !account.is_writable {
+        solana_program::msg!("Account not writable: {}", account.key);
+
+        return Err(VaultError::AccountNotWritable.into());
+    }
+
+    Ok(())
+}
+
+/// Fails unless `account` is a signer passed as writable, as fee payers and
+/// lamport senders must be.
+pub fn assert_writable_signer(
+    account: &solana_program::account_info::AccountInfo,
+) -> solana_program::entrypoint::ProgramResult {
+    assert_signer(account)?;
+    assert_writable(account)
+}
+
+/// Fails unless `account` is owned by `owner`.
+pub fn assert_owner(
+    account: &solana_program::account_info::AccountInfo,
+    owner: &solana_program::pubkey::Pubkey,
+) -> solana_program::entrypoint::ProgramResult {
+    if account.owner != owner {
+        solana_program::msg!(
+            "Account {} is owned by {}, expected {}",
+            account.key,
+            account.owner,
+            owner,
+        );
+
+        return Err(VaultError::InvalidAccountOwner.into());
+    }
+
+    Ok(())
+}
+
+/// Fails unless `account` is writable and holds state of `program_id`.
+pub fn assert_state_account(
+    account: &solana_program::account_info::AccountInfo,
+    program_id: &solana_program::pubkey::Pubkey,
+) -> solana_program::entrypoint::ProgramResult {
+    assert_writable(account)?;
+    assert_owner(account, program_id)
+}
+
+/// Fails unless `account` holds state of `program_id` or is an empty system
+/// account the program may still create.
+pub fn assert_owned_or_uninitialized(
+    account: &solana_program::account_info::AccountInfo,
+    program_id: &solana_program::pubkey::Pubkey,
+) -> solana_program::entrypoint::ProgramResult {
+    if account.owner == &solana_program::system_program::ID && account.data_is_empty() {
+        return Ok(());
+    }
+
+    assert_owner(account, program_id)
+}
+
+/// Fails unless `account` is the program `program_id`.
+pub fn assert_program(
+    account: &solana_program::account_info::AccountInfo,
+    program_id: &solana_program::pubkey::Pubkey,
+) -> solana_program::entrypoint::ProgramResult {
+    if account.key != program_id {
+        solana_program::msg!("Expected program {}, got {}", program_id, account.key);
+
+        return Err(VaultError::InvalidProgramAccount.into());
+    }
+
+    Ok(())
+}
+
+pub fn assert_system_program(
+    account: &solana_program::account_info::AccountInfo,
+) -> solana_program::entrypoint::ProgramResult {
+    assert_program(account, &solana_program::system_program::ID)
+}
+
+/// Checks the vault PDA every vault instruction takes second: it must hold
+/// vault state or still be uncreated, and be writable unless `instruction`
+/// only reads an existing vault.
+pub fn assert_vault_account(
+    account: &solana_program::account_info::AccountInfo,
+    program_id: &solana_program::pubkey::Pubkey,
+    instruction: &crate::instruction::VaultInstruction,
+) -> solana_program::entrypoint::ProgramResult {
+    use crate::instruction::VaultInstruction;
+
+    assert_owned_or_uninitialized(account, program_id)?;
+
+    let read_only = matches!(
+        instruction,
+        VaultInstruction::Balance { .. }
+            | VaultInstruction::ClaimStream { .. }
+            | VaultInstruction::Propose { .. }
+            | VaultInstruction::Approve { .. }
+            | VaultInstruction::ApproveDelegate { .. }
+            | VaultInstruction::RevokeDelegate { .. }
+            | VaultInstruction::AddDestination { .. }
+            | VaultInstruction::RemoveDestination { .. }
+            | VaultInstruction::ApproveRecovery { .. }
+            | VaultInstruction::CancelRecovery { .. }
+    );
+
+    if !read_only || account.data_is_empty() {
+        assert_writable(account)?;
+    }
+
+    Ok(())
+}
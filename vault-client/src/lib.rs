@@ -0,0 +1,262 @@
+use {
+    base64::Engine,
+    solana_client::{
+        client_error::{ClientError, ClientErrorKind},
+        rpc_client::RpcClient,
+        rpc_config::RpcSimulateTransactionConfig,
+    },
+    solana_program::{
+        instruction::{AccountMeta, Instruction},
+        pubkey::Pubkey,
+    },
+    solana_sdk::{message::Message, transaction::Transaction},
+    vault_program::state::{CONFIG_DISCRIMINATOR, CONFIG_VERSION, VAULT_VERSION},
+};
+
+pub use vault_program::{
+    error::VaultError,
+    instruction::VaultInstruction,
+    state::{Config, Vault},
+};
+
+#[derive(Clone, Debug, PartialEq, Eq, thiserror::Error)]
+pub enum DecodeError {
+    #[error("Data is not a vault program account")]
+    NotVaultAccount,
+
+    #[error("Unsupported account version {0}")]
+    UnsupportedVersion(u8),
+
+    #[error("Account data does not match the layout")]
+    Malformed,
+}
+
+/// Derives the vault PDA of `owner`. An empty `vault_name` selects the
+/// default vault.
+pub fn vault_address(program_id: &Pubkey, owner: &Pubkey, vault_name: &[u8]) -> (Pubkey, u8) {
+    Vault::find_address(program_id, owner, vault_name)
+}
+
+/// Derives the program config PDA.
+pub fn config_address(program_id: &Pubkey) -> (Pubkey, u8) {
+    Config::find_address(program_id)
+}
+
+/// Reads a vault through the instruction's return data. Fails if the vault
+/// doesn't exist.
+pub fn balance_ix(program_id: &Pubkey, owner: &Pubkey, vault_name: &[u8]) -> Instruction {
+    Instruction::new_with_borsh(
+        *program_id,
+        &VaultInstruction::Balance {
+            vault_name: vault_name.to_vec(),
+        },
+        vault_accounts(program_id, owner, vault_name),
+    )
+}
+
+/// Deposits `amount` lamports from `owner` into the vault. `treasury` is
+/// the config treasury, required once a deposit fee is configured.
+pub fn deposit_ix(
+    program_id: &Pubkey,
+    owner: &Pubkey,
+    vault_name: &[u8],
+    amount: u64,
+    treasury: Option<&Pubkey>,
+) -> Instruction {
+    Instruction::new_with_borsh(
+        *program_id,
+        &VaultInstruction::Deposit {
+            vault_name: vault_name.to_vec(),
+            amount,
+        },
+        fee_accounts(program_id, owner, vault_name, treasury),
+    )
+}
+
+/// Withdraws `amount` lamports from the vault to `owner`. `treasury` is the
+/// config treasury, required once a withdraw fee is configured.
+pub fn withdraw_ix(
+    program_id: &Pubkey,
+    owner: &Pubkey,
+    vault_name: &[u8],
+    amount: u64,
+    treasury: Option<&Pubkey>,
+) -> Instruction {
+    Instruction::new_with_borsh(
+        *program_id,
+        &VaultInstruction::Withdraw {
+            vault_name: vault_name.to_vec(),
+            amount,
+        },
+        fee_accounts(program_id, owner, vault_name, treasury),
+    )
+}
+
+/// Decodes a vault account, or the return data of a `Balance` instruction.
+/// Legacy vaults without a discriminator have to be migrated first.
+pub fn decode_vault(data: &[u8]) -> Result<Vault, DecodeError> {
+    match Vault::layout_version(data) {
+        None => Err(DecodeError::NotVaultAccount),
+        Some(VAULT_VERSION) => Vault::unpack(data).map_err(|_| DecodeError::Malformed),
+        Some(version) => Err(DecodeError::UnsupportedVersion(version)),
+    }
+}
+
+/// Decodes the program config account.
+pub fn decode_config(data: &[u8]) -> Result<Config, DecodeError> {
+    if data.len() <= 8 || data[..8] != CONFIG_DISCRIMINATOR {
+        return Err(DecodeError::NotVaultAccount);
+    }
+
+    if data[8] != CONFIG_VERSION {
+        return Err(DecodeError::UnsupportedVersion(data[8]));
+    }
+
+    Config::unpack(data).map_err(|_| DecodeError::Malformed)
+}
+
+/// Reads a vault of `owner` by simulating a `Balance` instruction and
+/// decoding its return data, so no transaction is sent or paid for. An
+/// empty `vault_name` selects the default vault.
+#[allow(clippy::result_large_err)]
+pub fn simulate_balance(
+    client: &RpcClient,
+    program_id: &Pubkey,
+    owner: &Pubkey,
+    vault_name: &[u8],
+) -> Result<Vault, ClientError> {
+    let instruction = balance_ix(program_id, owner, vault_name);
+    let transaction = Transaction::new_unsigned(Message::new(&[instruction], Some(owner)));
+
+    let config = RpcSimulateTransactionConfig {
+        sig_verify: false,
+        replace_recent_blockhash: true,
+        commitment: Some(client.commitment()),
+        ..RpcSimulateTransactionConfig::default()
+    };
+    let simulation = client
+        .simulate_transaction_with_config(&transaction, config)?
+        .value;
+
+    if let Some(error) = simulation.err {
+        return Err(error.into());
+    }
+
+    let return_data = simulation
+        .return_data
+        .filter(|return_data| return_data.program_id == program_id.to_string())
+        .ok_or_else(|| ClientErrorKind::Custom("Balance returned no data".to_string()))?;
+    let data = base64::engine::general_purpose::STANDARD
+        .decode(return_data.data.0)
+        .map_err(|error| ClientErrorKind::Custom(error.to_string()))?;
+
+    decode_vault(&data).map_err(|error| ClientErrorKind::Custom(error.to_string()).into())
+}
+
+/// Owner, vault, system program and config PDA, the accounts every vault
+/// instruction starts with.
+fn vault_accounts(program_id: &Pubkey, owner: &Pubkey, vault_name: &[u8]) -> Vec<AccountMeta> {
+    vec![
+        AccountMeta::new(*owner, true),
+        AccountMeta::new(vault_address(program_id, owner, vault_name).0, false),
+        AccountMeta::new_readonly(solana_program::system_program::ID, false),
+        AccountMeta::new_readonly(config_address(program_id).0, false),
+    ]
+}
+
+/// Vault accounts followed by the fee treasury, if given.
+fn fee_accounts(
+    program_id: &Pubkey,
+    owner: &Pubkey,
+    vault_name: &[u8],
+    treasury: Option<&Pubkey>,
+) -> Vec<AccountMeta> {
+    let mut accounts = vault_accounts(program_id, owner, vault_name);
+
+    if let Some(treasury) = treasury {
+        accounts.push(AccountMeta::new(*treasury, false));
+    }
+
+    accounts
+}
+
+#[cfg(test)]
+mod builder_tests {
+    use super::*;
+
+    #[test]
+    fn test_deposit_ix() {
+        let program_id = Pubkey::new_unique();
+        let owner = Pubkey::new_unique();
+        let treasury = Pubkey::new_unique();
+
+        let instruction = deposit_ix(&program_id, &owner, b"payroll", 1_000, Some(&treasury));
+
+        assert_eq!(instruction.program_id, program_id);
+        assert_eq!(
+            instruction.accounts,
+            vec![
+                AccountMeta::new(owner, true),
+                AccountMeta::new(
+                    Vault::find_address(&program_id, &owner, b"payroll").0,
+                    false
+                ),
+                AccountMeta::new_readonly(solana_program::system_program::ID, false),
+                AccountMeta::new_readonly(Config::find_address(&program_id).0, false),
+                AccountMeta::new(treasury, false),
+            ],
+        );
+        assert_eq!(
+            VaultInstruction::unpack(&instruction.data),
+            Ok(VaultInstruction::Deposit {
+                vault_name: b"payroll".to_vec(),
+                amount: 1_000,
+            }),
+        );
+    }
+
+    #[test]
+    fn test_balance_ix() {
+        let program_id = Pubkey::new_unique();
+        let owner = Pubkey::new_unique();
+
+        let instruction = balance_ix(&program_id, &owner, &[]);
+
+        assert_eq!(instruction.accounts.len(), 4);
+        assert_eq!(
+            instruction.accounts[1].pubkey,
+            Pubkey::find_program_address(&[owner.as_ref()], &program_id).0,
+        );
+        assert_eq!(
+            VaultInstruction::unpack(&instruction.data),
+            Ok(VaultInstruction::Balance { vault_name: vec![] }),
+        );
+    }
+
+    #[test]
+    fn test_decode_vault() {
+        let owner = Pubkey::new_unique();
+        let vault = Vault::new(owner, 255, b"payroll", 1_700_000_000);
+        let mut data = vec![0; Vault::LEN];
+        vault.pack(&mut data).unwrap();
+
+        assert_eq!(decode_vault(&data), Ok(vault));
+        assert_eq!(
+            decode_vault(&100u64.to_le_bytes()),
+            Err(DecodeError::NotVaultAccount)
+        );
+
+        data[8] = VAULT_VERSION - 1;
+        assert_eq!(
+            decode_vault(&data),
+            Err(DecodeError::UnsupportedVersion(VAULT_VERSION - 1)),
+        );
+
+        data[8] = VAULT_VERSION;
+        assert_eq!(
+            decode_vault(&data[..Vault::LEN - 1]),
+            Err(DecodeError::Malformed)
+        );
+        assert_eq!(decode_config(&data), Err(DecodeError::NotVaultAccount));
+    }
+}
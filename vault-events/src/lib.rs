@@ -0,0 +1,388 @@
+use {
+    base64::Engine,
+    borsh::BorshDeserialize,
+    solana_program::pubkey::Pubkey,
+    vault_program::event::{EVENT_DISCRIMINATOR, EVENT_HEADER_LEN},
+};
+
+pub use vault_program::event::{
+    BalanceAdjusted, Deposit, VaultCreated, VaultEvent, Withdraw, EVENT_VERSION,
+};
+
+#[derive(Clone, Debug, PartialEq, Eq, thiserror::Error)]
+pub enum DecodeError {
+    #[error("Data is not a vault event")]
+    NotVaultEvent,
+
+    #[error("Unsupported event version {0}")]
+    UnsupportedVersion(u8),
+
+    #[error("Event data does not match the schema")]
+    Malformed,
+
+    #[error("Program data is not valid base64")]
+    InvalidBase64,
+}
+
+/// Decodes one event as logged by the vault program.
+pub fn decode_event(data: &[u8]) -> Result<VaultEvent, DecodeError> {
+    if data.len() < EVENT_HEADER_LEN || data[..8] != EVENT_DISCRIMINATOR {
+        return Err(DecodeError::NotVaultEvent);
+    }
+
+    if data[8] != EVENT_VERSION {
+        return Err(DecodeError::UnsupportedVersion(data[8]));
+    }
+
+    VaultEvent::try_from_slice(&data[EVENT_HEADER_LEN..]).map_err(|_| DecodeError::Malformed)
+}
+
+/// Decodes, in order, the events `program_id` logged in a transaction's log
+/// messages. `Program data:` lines are attributed to the innermost program
+/// being invoked, so data logged by other programs, including those the
+/// vault program calls, is skipped.
+pub fn events_from_logs(
+    program_id: &Pubkey,
+    logs: &[String],
+) -> Result<Vec<VaultEvent>, DecodeError> {
+    let program_id = program_id.to_string();
+    let mut invoke_stack = Vec::new();
+    let mut events = Vec::new();
+
+    for log in logs {
+        if let Some(data) = log.strip_prefix("Program data: ") {
+            if invoke_stack.last() != Some(&program_id.as_str()) {
+                continue;
+            }
+
+            for field in data.split(' ') {
+                let bytes = base64::engine::general_purpose::STANDARD
+                    .decode(field)
+                    .map_err(|_| DecodeError::InvalidBase64)?;
+
+                events.push(decode_event(&bytes)?);
+            }
+        } else if let Some(rest) = log.strip_prefix("Program ") {
+            let mut words = rest.split(' ');
+
+            match (words.next(), words.next()) {
+                (Some(invoked), Some("invoke")) => invoke_stack.push(invoked),
+                (Some(_), Some("success" | "failed:")) => {
+                    invoke_stack.pop();
+                }
+                _ => {}
+            }
+        }
+    }
+
+    Ok(events)
+}
+
+#[cfg(test)]
+mod decoder_tests {
+    use {super::*, borsh::BorshSerialize};
+
+    fn deposit() -> VaultEvent {
+        VaultEvent::Deposit(Deposit {
+            vault: Pubkey::new_unique(),
+            depositor: Pubkey::new_unique(),
+            amount: 990_000,
+            fee: 10_000,
+            balance: 1_990_000,
+            timestamp: 1_700_000_000,
+        })
+    }
+
+    fn data_log(event: &VaultEvent) -> String {
+        format!(
+            "Program data: {}",
+            base64::engine::general_purpose::STANDARD.encode(event.pack()),
+        )
+    }
+
+    #[test]
+    fn test_decode_roundtrip() {
+        let event = deposit();
+
+        assert_eq!(decode_event(&event.pack()), Ok(event));
+    }
+
+    #[test]
+    fn test_decode_rejects_foreign_data() {
+        let mut data = deposit().pack();
+
+        assert_eq!(decode_event(&data[..4]), Err(DecodeError::NotVaultEvent));
+
+        data.push(0);
+        assert_eq!(decode_event(&data), Err(DecodeError::Malformed));
+
+        data[8] = EVENT_VERSION + 1;
+        assert_eq!(
+            decode_event(&data),
+            Err(DecodeError::UnsupportedVersion(EVENT_VERSION + 1)),
+        );
+
+        let mut unknown_variant = EVENT_DISCRIMINATOR.to_vec();
+        unknown_variant.push(EVENT_VERSION);
+        255u8.serialize(&mut unknown_variant).unwrap();
+        assert_eq!(decode_event(&unknown_variant), Err(DecodeError::Malformed));
+    }
+
+    #[test]
+    fn test_events_from_logs() {
+        let program_id = Pubkey::new_unique();
+        let other_program = Pubkey::new_unique();
+        let created = VaultEvent::VaultCreated(VaultCreated {
+            vault: Pubkey::new_unique(),
+            owner: Pubkey::new_unique(),
+            payer: Pubkey::new_unique(),
+            name: b"savings".to_vec(),
+            timestamp: 1_700_000_000,
+        });
+        let deposit = deposit();
+
+        let logs = vec![
+            format!("Program {program_id} invoke [1]"),
+            "Program log: Processing deposit".to_string(),
+            format!("Program {other_program} invoke [2]"),
+            data_log(&deposit),
+            format!("Program {other_program} success"),
+            data_log(&created),
+            data_log(&deposit),
+            format!("Program {program_id} consumed 4000 of 200000 compute units"),
+            format!("Program {program_id} success"),
+            format!("Program {other_program} invoke [1]"),
+            data_log(&created),
+            format!("Program {other_program} failed: custom program error: 0x0"),
+        ];
+
+        assert_eq!(
+            events_from_logs(&program_id, &logs),
+            Ok(vec![created, deposit]),
+        );
+    }
+}
+
+#[cfg(test)]
+mod program_tests {
+    use {
+        super::*,
+        solana_program::{
+            entrypoint::ProgramResult,
+            instruction::Instruction,
+            program_stubs::{set_syscall_stubs, SyscallStubs},
+        },
+        solana_program_test::{processor, ProgramTest},
+        solana_sdk::{
+            account_info::AccountInfo,
+            clock::Clock,
+            instruction::AccountMeta,
+            signature::{Keypair, Signer},
+            system_instruction,
+            transaction::Transaction,
+        },
+        vault_program::{instruction::VaultInstruction, state},
+    };
+
+    /// Program-test installs syscall stubs that print `sol_log_data` to
+    /// stdout instead of the transaction logs. This wrapper logs the data as
+    /// a program log, which the test maps back to the `Program data:` line a
+    /// validator would write.
+    struct LogDataStubs(Box<dyn SyscallStubs>);
+
+    /// Holds the stubs slot while the program-test stubs are being wrapped.
+    struct Placeholder;
+
+    impl SyscallStubs for Placeholder {}
+
+    const LOGGED_DATA_PREFIX: &str = "Program log: Program data: ";
+
+    impl SyscallStubs for LogDataStubs {
+        fn sol_log(&self, message: &str) {
+            self.0.sol_log(message);
+        }
+
+        fn sol_log_compute_units(&self) {
+            self.0.sol_log_compute_units();
+        }
+
+        fn sol_remaining_compute_units(&self) -> u64 {
+            self.0.sol_remaining_compute_units()
+        }
+
+        fn sol_invoke_signed(
+            &self,
+            instruction: &Instruction,
+            account_infos: &[AccountInfo],
+            signers_seeds: &[&[&[u8]]],
+        ) -> ProgramResult {
+            self.0
+                .sol_invoke_signed(instruction, account_infos, signers_seeds)
+        }
+
+        fn sol_get_clock_sysvar(&self, var_addr: *mut u8) -> u64 {
+            self.0.sol_get_clock_sysvar(var_addr)
+        }
+
+        fn sol_get_epoch_schedule_sysvar(&self, var_addr: *mut u8) -> u64 {
+            self.0.sol_get_epoch_schedule_sysvar(var_addr)
+        }
+
+        fn sol_get_fees_sysvar(&self, var_addr: *mut u8) -> u64 {
+            self.0.sol_get_fees_sysvar(var_addr)
+        }
+
+        fn sol_get_rent_sysvar(&self, var_addr: *mut u8) -> u64 {
+            self.0.sol_get_rent_sysvar(var_addr)
+        }
+
+        fn sol_get_epoch_rewards_sysvar(&self, var_addr: *mut u8) -> u64 {
+            self.0.sol_get_epoch_rewards_sysvar(var_addr)
+        }
+
+        fn sol_get_last_restart_slot(&self, var_addr: *mut u8) -> u64 {
+            self.0.sol_get_last_restart_slot(var_addr)
+        }
+
+        fn sol_get_return_data(&self) -> Option<(Pubkey, Vec<u8>)> {
+            self.0.sol_get_return_data()
+        }
+
+        fn sol_set_return_data(&self, data: &[u8]) {
+            self.0.sol_set_return_data(data);
+        }
+
+        fn sol_log_data(&self, fields: &[&[u8]]) {
+            let fields = fields
+                .iter()
+                .map(|field| base64::engine::general_purpose::STANDARD.encode(field))
+                .collect::<Vec<_>>();
+
+            self.0
+                .sol_log(&format!("Program data: {}", fields.join(" ")));
+        }
+
+        fn sol_get_processed_sibling_instruction(&self, index: usize) -> Option<Instruction> {
+            self.0.sol_get_processed_sibling_instruction(index)
+        }
+
+        fn sol_get_stack_height(&self) -> u64 {
+            self.0.sol_get_stack_height()
+        }
+    }
+
+    fn vault_instruction(
+        program_id: Pubkey,
+        user: &Pubkey,
+        instruction: VaultInstruction,
+    ) -> Instruction {
+        let vault = state::Vault::find_address(&program_id, user, instruction.vault_name()).0;
+
+        Instruction::new_with_borsh(
+            program_id,
+            &instruction,
+            vec![
+                AccountMeta::new(*user, true),
+                AccountMeta::new(vault, false),
+                AccountMeta::new_readonly(solana_program::system_program::ID, false),
+                AccountMeta::new_readonly(state::Config::find_address(&program_id).0, false),
+            ],
+        )
+    }
+
+    #[tokio::test]
+    async fn test_events_from_program_logs() {
+        let program_id = Pubkey::new_unique();
+        let program_test = ProgramTest::new(
+            "solana_program",
+            program_id,
+            processor!(vault_program::process_instruction),
+        );
+        let mut context = program_test.start_with_context().await;
+
+        // `start` has installed the program-test stubs by now; this is the
+        // only program test in the crate, so nothing races the swap.
+        let program_test_stubs = set_syscall_stubs(Box::new(Placeholder));
+        set_syscall_stubs(Box::new(LogDataStubs(program_test_stubs)));
+
+        let user = Keypair::new();
+        let vault = state::Vault::find_address(&program_id, &user.pubkey(), &[]).0;
+        let blockhash = context.get_new_latest_blockhash().await.unwrap();
+
+        let transaction = Transaction::new_signed_with_payer(
+            &[
+                system_instruction::transfer(&context.payer.pubkey(), &user.pubkey(), 10_000_000),
+                vault_instruction(
+                    program_id,
+                    &user.pubkey(),
+                    VaultInstruction::Deposit {
+                        vault_name: vec![],
+                        amount: 1_000_000,
+                    },
+                ),
+                system_instruction::transfer(&context.payer.pubkey(), &vault, 5_000),
+                vault_instruction(
+                    program_id,
+                    &user.pubkey(),
+                    VaultInstruction::Sync { vault_name: vec![] },
+                ),
+            ],
+            Some(&context.payer.pubkey()),
+            &[&context.payer, &user],
+            blockhash,
+        );
+
+        let result = context
+            .banks_client
+            .process_transaction_with_metadata(transaction)
+            .await
+            .unwrap();
+        result.result.unwrap();
+
+        let logs = result
+            .metadata
+            .unwrap()
+            .log_messages
+            .into_iter()
+            .map(|log| match log.strip_prefix(LOGGED_DATA_PREFIX) {
+                Some(data) => format!("Program data: {data}"),
+                None => log,
+            })
+            .collect::<Vec<_>>();
+
+        let timestamp = context
+            .banks_client
+            .get_sysvar::<Clock>()
+            .await
+            .unwrap()
+            .unix_timestamp;
+
+        assert_eq!(
+            events_from_logs(&program_id, &logs),
+            Ok(vec![
+                VaultEvent::VaultCreated(VaultCreated {
+                    vault,
+                    owner: user.pubkey(),
+                    payer: user.pubkey(),
+                    name: vec![],
+                    timestamp,
+                }),
+                VaultEvent::Deposit(Deposit {
+                    vault,
+                    depositor: user.pubkey(),
+                    amount: 1_000_000,
+                    fee: 0,
+                    balance: 1_000_000,
+                    timestamp,
+                }),
+                VaultEvent::BalanceAdjusted(BalanceAdjusted {
+                    vault,
+                    mint: Pubkey::default(),
+                    previous_balance: 1_000_000,
+                    balance: 1_005_000,
+                    timestamp,
+                }),
+            ]),
+        );
+    }
+}